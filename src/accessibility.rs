@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use crate::models::{LiftDisruption, StationAccessibility, StepFreeAccess, StopPoint};
+
+const ACCESSIBILITY_CATEGORY: &str = "Accessibility";
+
+impl StationAccessibility {
+    // Summarise a StopPoint's accessibility properties, along with any current lift
+    // outages reported at it
+    pub fn from_stop_point(stop_point: &StopPoint, lift_disruptions: &[LiftDisruption]) -> Self {
+        let naptan_id = stop_point
+            .naptan_id
+            .clone()
+            .or_else(|| stop_point.id.clone())
+            .unwrap_or_default();
+
+        let properties: BTreeMap<String, String> = stop_point
+            .additional_properties
+            .iter()
+            .filter(|p| p.category.as_deref() == Some(ACCESSIBILITY_CATEGORY))
+            .filter_map(|p| Some((p.key.clone()?, p.value.clone()?)))
+            .collect();
+
+        let lift_disruptions = lift_disruptions
+            .iter()
+            .filter(|d| d.naptan_code.as_deref() == Some(naptan_id.as_str()))
+            .cloned()
+            .collect();
+
+        Self {
+            step_free: step_free_access(&properties, stop_point.accessibility_summary.as_deref()),
+            access_via_lift: properties.get("AccessViaLift").cloned(),
+            accessibility_summary: stop_point.accessibility_summary.clone(),
+            common_name: stop_point.common_name.clone(),
+            naptan_id,
+            properties,
+            lift_disruptions,
+        }
+    }
+}
+
+// Work out the level of step-free access, preferring explicit step-free properties,
+// then the free-text summary, then whether the station is reachable by lift
+fn step_free_access(
+    properties: &BTreeMap<String, String>,
+    summary: Option<&str>,
+) -> StepFreeAccess {
+    let is_yes = |key: &str| {
+        properties
+            .get(key)
+            .is_some_and(|v| v.eq_ignore_ascii_case("yes"))
+    };
+
+    if is_yes("StepFreeToTrain") {
+        return StepFreeAccess::ToTrain;
+    }
+    if is_yes("StepFreeToPlatform") {
+        return StepFreeAccess::ToPlatform;
+    }
+
+    if let Some(summary) = summary.map(str::to_lowercase) {
+        let step_free = summary.contains("step-free") || summary.contains("step free");
+        if summary.contains("no step-free") || summary.contains("no step free") {
+            return StepFreeAccess::None;
+        }
+        if step_free && summary.contains("partial") {
+            return StepFreeAccess::Partial;
+        }
+        if step_free && summary.contains("to train") {
+            return StepFreeAccess::ToTrain;
+        }
+        if step_free && summary.contains("to platform") {
+            return StepFreeAccess::ToPlatform;
+        }
+    }
+
+    match properties.get("AccessViaLift").map(|v| v.to_lowercase()) {
+        Some(v) if v == "yes" => StepFreeAccess::ToPlatform,
+        Some(v) if v == "partial" => StepFreeAccess::Partial,
+        Some(v) if v == "no" => StepFreeAccess::None,
+        _ => StepFreeAccess::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_step_free_access() {
+        assert_eq!(
+            step_free_access(&properties(&[("StepFreeToTrain", "Yes")]), None),
+            StepFreeAccess::ToTrain
+        );
+        assert_eq!(
+            step_free_access(
+                &properties(&[]),
+                Some("Step-free access from street to platform")
+            ),
+            StepFreeAccess::ToPlatform
+        );
+        assert_eq!(
+            step_free_access(&properties(&[("AccessViaLift", "No")]), None),
+            StepFreeAccess::None
+        );
+        assert_eq!(
+            step_free_access(&properties(&[]), None),
+            StepFreeAccess::Unknown
+        );
+    }
+}
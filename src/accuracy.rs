@@ -0,0 +1,291 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+use crate::headways::percentile;
+use crate::models::{Prediction, PredictionAccuracy};
+use crate::tfl::TflClient;
+
+// Prediction accuracy. A background poller fetches each line's predictions and
+// keeps the history of every vehicle's predicted arrival at each stop. When a
+// prediction that had counted down to (nearly) zero drops out of the feed, the
+// train has arrived: its last expected arrival is taken as the actual one, and each
+// earlier prediction is scored against it. Predictions that vanish while the train
+// is still some way off (cancellations, reroutes) aren't scored.
+
+// Often enough to see each prediction count down to the platform
+pub const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+// A prediction last seen this close to arrival that drops out has arrived
+const ARRIVAL_WINDOW: i32 = 60;
+// How long scored arrivals count towards the statistics
+const HISTORY_WINDOW: Duration = Duration::hours(3);
+// Predictions kept per vehicle and stop, an hour's worth of polls
+const MAX_OBSERVATIONS: usize = 120;
+
+// Horizon buckets: label and the seconds ahead they start at
+const HORIZONS: &[(&str, i64)] = &[
+    ("0-2m", 0),
+    ("2-5m", 120),
+    ("5-10m", 300),
+    ("10-20m", 600),
+    ("20m+", 1200),
+];
+
+struct Observation {
+    time_to_station: i32,
+    expected_arrival: DateTime<Utc>,
+}
+
+// A train's arrival and the errors of the predictions made for it
+struct Arrival {
+    line_id: String,
+    arrived_at: DateTime<Utc>,
+    // (seconds ahead, seconds late)
+    errors: Vec<(i64, i64)>,
+}
+
+#[derive(Default)]
+struct HistoryState {
+    // By line, vehicle and stop
+    tracked: HashMap<(String, String, String), Vec<Observation>>,
+    arrivals: VecDeque<Arrival>,
+}
+
+pub struct PredictionHistory {
+    state: Mutex<HistoryState>,
+}
+
+impl PredictionHistory {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(HistoryState::default()),
+        }
+    }
+
+    // Start polling the given lines, for as long as the history is in use. With no
+    // lines there's no polling, and no statistics.
+    pub fn start(
+        tfl_client: Arc<TflClient>,
+        lines: Vec<String>,
+        interval: std::time::Duration,
+    ) -> Arc<Self> {
+        let history = Arc::new(Self::new());
+        if lines.is_empty() {
+            info!("Prediction accuracy polling disabled");
+            return history;
+        }
+        let weak = Arc::downgrade(&history);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                for line_id in &lines {
+                    let predictions = tfl_client.get_arrivals_by_line(line_id).await;
+                    let Some(history) = weak.upgrade() else {
+                        return;
+                    };
+                    match predictions {
+                        Ok(predictions) => history.record(line_id, &predictions, Utc::now()),
+                        // A failed poll isn't every train arriving; wait for the next
+                        Err(e) => warn!("Prediction poll for {} failed: {}", line_id, e),
+                    }
+                }
+            }
+        });
+
+        history
+    }
+
+    // Add a line's latest predictions to the history, scoring the trains that have
+    // arrived since its last poll
+    pub fn record(&self, line_id: &str, predictions: &[Prediction], now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+
+        let mut seen = HashSet::new();
+        for prediction in predictions {
+            let (Some(vehicle_id), Some(naptan_id), Some(time_to_station), Some(expected_arrival)) = (
+                &prediction.vehicle_id,
+                &prediction.naptan_id,
+                prediction.time_to_station,
+                prediction.expected_arrival,
+            ) else {
+                continue;
+            };
+            let key = (line_id.to_string(), vehicle_id.clone(), naptan_id.clone());
+            let observations = state.tracked.entry(key.clone()).or_default();
+            if observations.len() == MAX_OBSERVATIONS {
+                observations.remove(0);
+            }
+            observations.push(Observation {
+                time_to_station,
+                expected_arrival,
+            });
+            seen.insert(key);
+        }
+
+        let gone: Vec<_> = state
+            .tracked
+            .keys()
+            .filter(|key| key.0 == line_id && !seen.contains(*key))
+            .cloned()
+            .collect();
+        for key in gone {
+            let observations = state.tracked.remove(&key).unwrap_or_default();
+            let Some(last) = observations.last() else {
+                continue;
+            };
+            if last.time_to_station > ARRIVAL_WINDOW {
+                debug!(
+                    "Prediction for {} at {} dropped before arrival",
+                    key.1, key.2
+                );
+                continue;
+            }
+
+            // The last prediction is the arrival itself, so only earlier ones are
+            // scored against it
+            let arrived_at = last.expected_arrival;
+            let earlier = &observations[..observations.len() - 1];
+            if earlier.is_empty() {
+                continue;
+            }
+            let errors = earlier
+                .iter()
+                .map(|o| {
+                    let error = (arrived_at - o.expected_arrival).num_seconds();
+                    (o.time_to_station as i64, error)
+                })
+                .collect();
+            state.arrivals.push_back(Arrival {
+                line_id: key.0,
+                arrived_at,
+                errors,
+            });
+        }
+
+        while state
+            .arrivals
+            .front()
+            .is_some_and(|arrival| now - arrival.arrived_at > HISTORY_WINDOW)
+        {
+            state.arrivals.pop_front();
+        }
+    }
+
+    // Accuracy for each line and horizon with scored predictions, optionally only
+    // for some lines
+    pub fn accuracy(&self, line_ids: Option<&[&str]>) -> Vec<PredictionAccuracy> {
+        let state = self.state.lock().unwrap();
+
+        // Errors and the number of arrivals they're from, by line and horizon
+        let mut buckets: BTreeMap<(&str, usize), (Vec<i64>, usize)> = BTreeMap::new();
+        for arrival in &state.arrivals {
+            if line_ids.is_some_and(|ids| !ids.contains(&arrival.line_id.as_str())) {
+                continue;
+            }
+            let mut horizons = HashSet::new();
+            for &(ahead, error) in &arrival.errors {
+                let horizon = HORIZONS
+                    .iter()
+                    .rposition(|(_, from)| ahead >= *from)
+                    .unwrap_or(0);
+                let bucket = buckets.entry((&arrival.line_id, horizon)).or_default();
+                bucket.0.push(error);
+                if horizons.insert(horizon) {
+                    bucket.1 += 1;
+                }
+            }
+        }
+
+        buckets
+            .into_iter()
+            .map(|((line_id, horizon), (errors, arrivals))| {
+                let mut absolute: Vec<i64> = errors.iter().map(|e| e.abs()).collect();
+                absolute.sort_unstable();
+                let samples = errors.len() as f64;
+                PredictionAccuracy {
+                    line_id: line_id.to_string(),
+                    horizon: HORIZONS[horizon].0.to_string(),
+                    horizon_from: HORIZONS[horizon].1,
+                    horizon_to: HORIZONS.get(horizon + 1).map(|(_, from)| *from),
+                    samples: errors.len(),
+                    arrivals,
+                    mean_error: errors.iter().sum::<i64>() as f64 / samples,
+                    mean_absolute_error: absolute.iter().sum::<i64>() as f64 / samples,
+                    p90_absolute_error: percentile(&absolute, 90),
+                    within_minute: absolute.iter().filter(|e| **e <= 60).count() as f64 / samples,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2024-01-01T{}Z", time).parse().unwrap()
+    }
+
+    fn prediction(vehicle_id: &str, time_to_station: i32, expected_arrival: &str) -> Prediction {
+        serde_json::from_value(json!({
+            "vehicleId": vehicle_id,
+            "naptanId": "940GZZLUVIC",
+            "timeToStation": time_to_station,
+            "expectedArrival": at(expected_arrival),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_score_arrivals() {
+        let history = PredictionHistory::new();
+        history.record(
+            "victoria",
+            &[
+                prediction("1", 400, "12:06:40"),
+                prediction("2", 900, "12:15:00"),
+            ],
+            at("12:00:00"),
+        );
+        history.record(
+            "victoria",
+            &[
+                prediction("1", 150, "12:05:20"),
+                prediction("2", 700, "12:15:00"),
+            ],
+            at("12:03:00"),
+        );
+        history.record(
+            "victoria",
+            &[
+                prediction("1", 10, "12:05:30"),
+                prediction("2", 600, "12:15:00"),
+            ],
+            at("12:05:20"),
+        );
+        // Train 1 has arrived; train 2 was withdrawn far from the station
+        history.record("victoria", &[], at("12:06:00"));
+
+        // The final prediction is the arrival, and isn't scored itself
+        let accuracy = history.accuracy(None);
+        let horizons: Vec<&str> = accuracy.iter().map(|a| a.horizon.as_str()).collect();
+        assert_eq!(horizons, ["2-5m", "5-10m"]);
+        assert!(accuracy.iter().all(|a| a.samples == 1 && a.arrivals == 1));
+        assert_eq!(accuracy[0].mean_error, 10.0);
+        assert_eq!(accuracy[0].within_minute, 1.0);
+        // The earliest prediction had the train arriving 70 seconds later than it did
+        assert_eq!(accuracy[1].mean_error, -70.0);
+        assert_eq!(accuracy[1].p90_absolute_error, 70);
+        assert_eq!(accuracy[1].within_minute, 0.0);
+        assert_eq!(accuracy[1].horizon_to, Some(600));
+
+        assert!(history.accuracy(Some(&["central"])).is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt::Write;
+
+use crate::models::{Departure, DestinationDepartures, MetaData, PlatformBoard, Prediction};
+
+// Departure boards: a station's predictions grouped by platform then destination,
+// with countdowns worded as station displays show them, and plain-text and HTML
+// renderings for screens that just want to show the board.
+
+// Suffixes TfL adds to destination names that displays leave off
+const STATION_SUFFIXES: &[&str] = &[
+    " Underground Station",
+    " DLR Station",
+    " (London) Rail Station",
+    " Rail Station",
+];
+
+// "due" within the minute, then whole minutes
+fn due(time_to_station: i32) -> String {
+    if time_to_station < 60 {
+        "due".to_string()
+    } else {
+        format!("{} min", time_to_station / 60)
+    }
+}
+
+fn destination_name(prediction: &Prediction) -> String {
+    let name = prediction
+        .destination_name
+        .as_deref()
+        .or(prediction.towards.as_deref())
+        .unwrap_or("Check front of train");
+    STATION_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(name)
+        .to_string()
+}
+
+// The next `count` departures from each platform, soonest first. Platforms come out
+// in order of their next departure, and destinations in order of theirs.
+pub fn build(mut predictions: Vec<Prediction>, count: usize) -> Vec<PlatformBoard> {
+    predictions.retain(|p| p.time_to_station.is_some());
+    predictions.sort_by_key(|p| p.time_to_station);
+
+    let mut boards: Vec<PlatformBoard> = Vec::new();
+    let mut departures_left: Vec<usize> = Vec::new();
+    for prediction in predictions {
+        let platform_name = prediction.platform_name.clone().unwrap_or_default();
+        let index = match boards.iter().position(|b| b.platform_name == platform_name) {
+            Some(i) => i,
+            None => {
+                boards.push(PlatformBoard {
+                    station_name: prediction.station_name.clone(),
+                    platform_name,
+                    destinations: Vec::new(),
+                });
+                departures_left.push(count);
+                boards.len() - 1
+            }
+        };
+        if departures_left[index] == 0 {
+            continue;
+        }
+        departures_left[index] -= 1;
+
+        let destination_name = destination_name(&prediction);
+        let time_to_station = prediction.time_to_station.unwrap_or_default();
+        let departure = Departure {
+            line_id: prediction.line_id,
+            line_name: prediction.line_name,
+            vehicle_id: prediction.vehicle_id,
+            time_to_station,
+            due: due(time_to_station),
+            expected_arrival: prediction.expected_arrival,
+            current_location: prediction.current_location.filter(|l| !l.is_empty()),
+        };
+
+        let destinations = &mut boards[index].destinations;
+        match destinations
+            .iter_mut()
+            .find(|d| d.destination_name == destination_name)
+        {
+            Some(destination) => destination.departures.push(departure),
+            None => destinations.push(DestinationDepartures {
+                destination_name,
+                departures: vec![departure],
+            }),
+        }
+    }
+    boards
+}
+
+// Departures of a platform in time order, for the line-per-train renderings
+fn in_order(board: &PlatformBoard) -> Vec<(&str, &Departure)> {
+    let mut departures: Vec<(&str, &Departure)> = board
+        .destinations
+        .iter()
+        .flat_map(|d| {
+            d.departures
+                .iter()
+                .map(|dep| (d.destination_name.as_str(), dep))
+        })
+        .collect();
+    departures.sort_by_key(|(_, departure)| departure.time_to_station);
+    departures
+}
+
+fn station_name(boards: &[PlatformBoard], context: &MetaData) -> String {
+    boards
+        .iter()
+        .find_map(|b| b.station_name.clone())
+        .unwrap_or_else(|| context.query.clone())
+}
+
+pub fn to_text(context: &MetaData, boards: &[PlatformBoard]) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "{}", station_name(boards, context));
+    if boards.is_empty() {
+        let _ = writeln!(text, "\nNo departures");
+    }
+    for board in boards {
+        let _ = writeln!(text, "\n{}", board.platform_name);
+        for (position, (destination, departure)) in in_order(board).into_iter().enumerate() {
+            let _ = writeln!(
+                text,
+                "{:>2}  {:<32} {:>6}",
+                position + 1,
+                destination,
+                departure.due
+            );
+            if let Some(location) = &departure.current_location {
+                let _ = writeln!(text, "    {}", location);
+            }
+        }
+    }
+    text
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn to_html(context: &MetaData, boards: &[PlatformBoard]) -> String {
+    let station = escape(&station_name(boards, context));
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta http-equiv=\"refresh\" content=\"30\">\n<title>{station}</title>\n</head>\n\
+         <body>\n<h1>{station}</h1>\n"
+    );
+    if boards.is_empty() {
+        html.push_str("<p>No departures</p>\n");
+    }
+    for board in boards {
+        let _ = writeln!(
+            html,
+            "<section>\n<h2>{}</h2>\n<table>",
+            escape(&board.platform_name)
+        );
+        for (position, (destination, departure)) in in_order(board).into_iter().enumerate() {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                position + 1,
+                escape(destination),
+                escape(&departure.due),
+                escape(departure.current_location.as_deref().unwrap_or_default())
+            );
+        }
+        html.push_str("</table>\n</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn prediction(platform: &str, destination: &str, time_to_station: i32) -> Prediction {
+        serde_json::from_value(json!({
+            "stationName": "Victoria Underground Station",
+            "lineId": "victoria",
+            "platformName": platform,
+            "destinationName": destination,
+            "timeToStation": time_to_station,
+            "currentLocation": "At Pimlico",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_board() {
+        let boards = build(
+            vec![
+                prediction(
+                    "Southbound - Platform 4",
+                    "Brixton Underground Station",
+                    400,
+                ),
+                prediction(
+                    "Northbound - Platform 3",
+                    "Walthamstow Central Underground Station",
+                    150,
+                ),
+                prediction("Southbound - Platform 4", "Brixton Underground Station", 20),
+                prediction(
+                    "Northbound - Platform 3",
+                    "Seven Sisters Underground Station",
+                    300,
+                ),
+                prediction(
+                    "Northbound - Platform 3",
+                    "Walthamstow Central Underground Station",
+                    500,
+                ),
+                prediction(
+                    "Southbound - Platform 4",
+                    "Brixton Underground Station",
+                    700,
+                ),
+            ],
+            2,
+        );
+
+        assert_eq!(boards.len(), 2);
+        assert_eq!(boards[0].platform_name, "Southbound - Platform 4");
+        assert_eq!(boards[0].destinations[0].destination_name, "Brixton");
+        let dues: Vec<&str> = boards[0].destinations[0]
+            .departures
+            .iter()
+            .map(|d| d.due.as_str())
+            .collect();
+        assert_eq!(dues, ["due", "6 min"]);
+
+        // Only the next two from the platform, grouped by destination
+        let destinations: Vec<&str> = boards[1]
+            .destinations
+            .iter()
+            .map(|d| d.destination_name.as_str())
+            .collect();
+        assert_eq!(destinations, ["Walthamstow Central", "Seven Sisters"]);
+        assert_eq!(boards[1].destinations[0].departures.len(), 1);
+    }
+
+    #[test]
+    fn test_render_board() {
+        let context = crate::routes::create_metadata(std::time::Instant::now(), "940GZZLUVIC");
+        let boards = build(vec![prediction("Platform <1>", "Brixton", 130)], 3);
+
+        let text = to_text(&context, &boards);
+        assert!(text.starts_with("Victoria Underground Station\n\nPlatform <1>\n 1  Brixton"));
+        assert!(text.contains("2 min\n    At Pimlico\n"));
+
+        let html = to_html(&context, &boards);
+        assert!(html.contains("<h2>Platform &lt;1&gt;</h2>"));
+        assert!(html.contains("<td>Brixton</td><td>2 min</td><td>At Pimlico</td>"));
+    }
+}
@@ -0,0 +1,440 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+use crate::models::{ChangeKind, Disruption, DisruptionChange};
+use crate::tfl::TflClient;
+
+// A feed of changes to disruptions. A background poller fetches each mode's
+// disruptions, diffs them against the previous snapshot by a stable identity, and
+// records `added`, `updated` and `resolved` changes. Recent changes are kept for
+// clients catching up with `since=`, and broadcast to streaming clients as they
+// happen. The first snapshot after startup reports every current disruption as
+// added.
+//
+// Sequence numbers restart with the server, so change ids carry the boot's epoch
+// as well (`<epoch>-<sequence>`). A client resuming from another boot's id, or from
+// a sequence number this boot hasn't reached, gets every kept change again, flagged
+// as a gap, as does one resuming from before the oldest change still kept.
+
+// TfL updates disruptions far less often than predictions
+pub const POLL_INTERVAL: Duration = Duration::from_secs(30);
+// Changes kept for clients catching up
+const MAX_CHANGES: usize = 1000;
+// Changes buffered per streaming client before it starts missing them
+const BROADCAST_CAPACITY: usize = 256;
+
+// The current disruptions for a mode, by identity
+type ModeSnapshot = BTreeMap<String, Disruption>;
+
+pub struct DisruptionFeed {
+    epoch: String,
+    started_at: DateTime<Utc>,
+    state: Mutex<FeedState>,
+    sender: broadcast::Sender<DisruptionChange>,
+}
+
+#[derive(Default)]
+struct FeedState {
+    next_sequence: u64,
+    snapshots: HashMap<String, ModeSnapshot>,
+    changes: VecDeque<DisruptionChange>,
+    // The last change no longer kept, if any have been let go
+    dropped_sequence: u64,
+    dropped_at: Option<DateTime<Utc>>,
+}
+
+// Where a client resumes the feed from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resume {
+    Start,
+    // A change id, or a bare sequence number taken to be from this boot
+    Sequence {
+        epoch: Option<String>,
+        sequence: u64,
+    },
+    Time(DateTime<Utc>),
+}
+
+impl Resume {
+    // A change id (`<epoch>-<sequence>`), sequence number or RFC 3339 time
+    pub fn parse(since: &str) -> Option<Self> {
+        if let Ok(sequence) = since.parse() {
+            return Some(Resume::Sequence {
+                epoch: None,
+                sequence,
+            });
+        }
+        if let Some((epoch, sequence)) = since.split_once('-') {
+            if let (true, Ok(sequence)) = (
+                epoch.chars().all(|c| c.is_ascii_hexdigit()),
+                sequence.parse(),
+            ) {
+                return Some(Resume::Sequence {
+                    epoch: Some(epoch.to_string()),
+                    sequence,
+                });
+            }
+        }
+        DateTime::parse_from_rfc3339(since)
+            .ok()
+            .map(|time| Resume::Time(time.with_timezone(&Utc)))
+    }
+}
+
+// Kept changes after a resume point, and whether some in between are missing
+pub struct Catchup {
+    pub changes: Vec<DisruptionChange>,
+    pub gap: bool,
+}
+
+impl DisruptionFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let started_at = Utc::now();
+        Self {
+            epoch: format!("{:x}", started_at.timestamp_millis()),
+            started_at,
+            state: Mutex::new(FeedState {
+                next_sequence: 1,
+                ..Default::default()
+            }),
+            sender,
+        }
+    }
+
+    // Start polling the given modes, for as long as the feed is in use
+    pub fn start(tfl_client: Arc<TflClient>, modes: Vec<String>, interval: Duration) -> Arc<Self> {
+        let feed = Arc::new(Self::new());
+        let weak = Arc::downgrade(&feed);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                for mode in &modes {
+                    let disruptions = tfl_client.get_disruptions_by_mode(mode).await;
+                    let Some(feed) = weak.upgrade() else {
+                        return;
+                    };
+                    match disruptions {
+                        Ok(disruptions) => feed.record(mode, disruptions, Utc::now()),
+                        // A failed poll isn't a resolution; keep the last snapshot
+                        Err(e) => warn!("Disruption poll for {} failed: {}", mode, e),
+                    }
+                }
+            }
+        });
+
+        feed
+    }
+
+    // Diff a mode's latest disruptions against its previous snapshot
+    pub fn record(&self, mode: &str, disruptions: Vec<Disruption>, now: DateTime<Utc>) {
+        let current: ModeSnapshot = disruption_ids(&disruptions)
+            .into_iter()
+            .zip(disruptions)
+            .collect();
+
+        let mut state = self.state.lock().unwrap();
+        let baseline = !state.snapshots.contains_key(mode);
+        let previous = state.snapshots.remove(mode).unwrap_or_default();
+
+        let mut changes = Vec::new();
+        for (id, disruption) in &current {
+            match previous.get(id) {
+                None => changes.push((ChangeKind::Added, id, disruption)),
+                Some(before) if !same_content(before, disruption) => {
+                    changes.push((ChangeKind::Updated, id, disruption))
+                }
+                Some(_) => {}
+            }
+        }
+        for (id, disruption) in &previous {
+            if !current.contains_key(id) {
+                changes.push((ChangeKind::Resolved, id, disruption));
+            }
+        }
+
+        for (kind, id, disruption) in changes {
+            let change = DisruptionChange {
+                id: format!("{}-{}", self.epoch, state.next_sequence),
+                sequence: state.next_sequence,
+                kind,
+                mode: mode.to_string(),
+                disruption_id: id.clone(),
+                detected_at: now,
+                disruption: disruption.clone(),
+                baseline,
+            };
+            debug!("Disruption {:?} in {}: {}", kind, mode, id);
+            state.next_sequence += 1;
+            if state.changes.len() == MAX_CHANGES {
+                if let Some(dropped) = state.changes.pop_front() {
+                    state.dropped_sequence = dropped.sequence;
+                    state.dropped_at = Some(dropped.detected_at);
+                }
+            }
+            state.changes.push_back(change.clone());
+            // Nobody may be streaming
+            let _ = self.sender.send(change);
+        }
+
+        state.snapshots.insert(mode.to_string(), current);
+    }
+
+    // Kept changes after the given sequence number
+    pub fn since_sequence(&self, sequence: u64) -> Vec<DisruptionChange> {
+        let state = self.state.lock().unwrap();
+        state
+            .changes
+            .iter()
+            .filter(|change| change.sequence > sequence)
+            .cloned()
+            .collect()
+    }
+
+    // Kept changes after a client's resume point
+    pub fn resume(&self, from: &Resume) -> Catchup {
+        let state = self.state.lock().unwrap();
+        let kept_after = |sequence: u64| -> Vec<DisruptionChange> {
+            state
+                .changes
+                .iter()
+                .filter(|change| change.sequence > sequence)
+                .cloned()
+                .collect()
+        };
+
+        match from {
+            Resume::Start => Catchup {
+                changes: kept_after(0),
+                gap: false,
+            },
+            Resume::Sequence { epoch, sequence } => {
+                // Another boot's numbering can't be compared with this one's
+                let stale = epoch.as_ref().is_some_and(|epoch| *epoch != self.epoch)
+                    || *sequence >= state.next_sequence;
+                let after = if stale { 0 } else { *sequence };
+                Catchup {
+                    changes: kept_after(after),
+                    gap: stale || state.dropped_sequence > after,
+                }
+            }
+            Resume::Time(time) => Catchup {
+                changes: state
+                    .changes
+                    .iter()
+                    .filter(|change| change.detected_at > *time)
+                    .cloned()
+                    .collect(),
+                gap: *time < self.started_at || state.dropped_at.is_some_and(|at| at > *time),
+            },
+        }
+    }
+
+    // Changes as they're recorded. Subscribe before reading kept changes so none
+    // fall between the two.
+    pub fn subscribe(&self) -> broadcast::Receiver<DisruptionChange> {
+        self.sender.subscribe()
+    }
+}
+
+// A disruption's identity across polls: what it is, where, and when it started,
+// but not its wording, which TfL revises as it develops
+pub fn disruption_id(disruption: &Disruption) -> String {
+    let mut routes: Vec<(Option<&str>, Option<&str>)> = disruption
+        .affected_routes
+        .iter()
+        .map(|route| (route.line_id.as_deref(), route.id.as_deref()))
+        .collect();
+    routes.sort();
+    routes.dedup();
+
+    digest(&json!([
+        disruption.category,
+        disruption.disruption_type,
+        routes,
+        disruption.created,
+    ]))
+}
+
+// Identities for a mode's disruptions. Disruptions that only differ in wording
+// (often ones with no affected routes) would share an identity, so those are told
+// apart by their description too.
+pub fn disruption_ids(disruptions: &[Disruption]) -> Vec<String> {
+    let ids: Vec<String> = disruptions.iter().map(disruption_id).collect();
+    ids.iter()
+        .zip(disruptions)
+        .map(|(id, disruption)| {
+            if ids.iter().filter(|other| *other == id).count() == 1 {
+                id.clone()
+            } else {
+                digest(&json!([id, disruption.description]))
+            }
+        })
+        .collect()
+}
+
+// First 64 bits of the SHA-256 of a JSON value, in hex
+fn digest(value: &serde_json::Value) -> String {
+    Sha256::digest(value.to_string().as_bytes())[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn same_content(a: &Disruption, b: &Disruption) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn disruption(line_id: &str, description: &str) -> Disruption {
+        serde_json::from_value(json!({
+            "category": "RealTime",
+            "type": "lineInfo",
+            "description": description,
+            "created": "2024-01-01T12:00:00Z",
+            "affectedRoutes": [{ "lineId": line_id, "id": format!("{}-route", line_id) }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let feed = DisruptionFeed::new();
+        let now = Utc::now();
+        let mut stream = feed.subscribe();
+
+        feed.record(
+            "tube",
+            vec![
+                disruption("victoria", "Minor delays"),
+                disruption("central", "Severe delays"),
+            ],
+            now,
+        );
+        feed.record(
+            "tube",
+            vec![disruption("victoria", "Good service soon")],
+            now,
+        );
+
+        let kinds: Vec<(u64, ChangeKind)> = feed
+            .resume(&Resume::Start)
+            .changes
+            .iter()
+            .map(|change| (change.sequence, change.kind))
+            .collect();
+        assert_eq!(kinds.len(), 4);
+        assert_eq!(
+            kinds[2..],
+            [(3, ChangeKind::Updated), (4, ChangeKind::Resolved)]
+        );
+
+        let later = feed.since_sequence(2);
+        assert_eq!(
+            later[0].disruption_id,
+            disruption_id(&disruption("victoria", ""))
+        );
+        assert_eq!(
+            later[1].disruption.description.as_deref(),
+            Some("Severe delays")
+        );
+        assert_eq!(stream.try_recv().unwrap().sequence, 1);
+        assert!(feed.since_sequence(0)[0].baseline);
+        assert!(!later[0].baseline);
+
+        // Unchanged disruptions aren't reported again
+        feed.record(
+            "tube",
+            vec![disruption("victoria", "Good service soon")],
+            now,
+        );
+        assert!(feed.since_sequence(4).is_empty());
+    }
+
+    fn no_routes(description: &str) -> Disruption {
+        serde_json::from_value(json!({
+            "category": "Information",
+            "type": "lineInfo",
+            "description": description,
+            "created": "2024-01-01T12:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_colliding_identities() {
+        let feed = DisruptionFeed::new();
+        let now = Utc::now();
+        let first = no_routes("Step-free access unavailable at Oval");
+        let second = no_routes("Reduced service on Sunday");
+
+        let ids = disruption_ids(&[first.clone(), second.clone()]);
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[0], disruption_id(&first));
+
+        feed.record("tube", vec![first.clone(), second.clone()], now);
+        assert_eq!(feed.resume(&Resume::Start).changes.len(), 2);
+        // Listed the other way round, they're the same two disruptions
+        feed.record("tube", vec![second, first], now);
+        assert_eq!(feed.resume(&Resume::Start).changes.len(), 2);
+    }
+
+    #[test]
+    fn test_resume_points() {
+        let feed = DisruptionFeed::new();
+        let now = Utc::now();
+        feed.record(
+            "tube",
+            vec![disruption("victoria", ""), disruption("central", "")],
+            now,
+        );
+        let first_id = feed.resume(&Resume::Start).changes[0].id.clone();
+        assert_eq!(
+            Resume::parse(&first_id),
+            Some(Resume::Sequence {
+                epoch: Some(feed.epoch.clone()),
+                sequence: 1
+            })
+        );
+
+        let catchup = feed.resume(&Resume::parse(&first_id).unwrap());
+        assert_eq!((catchup.changes.len(), catchup.gap), (1, false));
+
+        // A sequence number from before a restart is replayed from the start
+        for since in ["523", "18f0a3b2c41-2"] {
+            let catchup = feed.resume(&Resume::parse(since).unwrap());
+            assert_eq!((catchup.changes.len(), catchup.gap), (2, true));
+        }
+
+        // As is anything older than the changes still kept
+        let catchup = feed.resume(&Resume::Time(now - chrono::Duration::days(1)));
+        assert!(catchup.gap);
+        {
+            let mut state = feed.state.lock().unwrap();
+            state.dropped_sequence = 1;
+        }
+        let catchup = feed.resume(&Resume::Sequence {
+            epoch: None,
+            sequence: 0,
+        });
+        assert!(catchup.gap);
+        assert_eq!(
+            Resume::parse("2024-01-01T12:00:00Z"),
+            Some(Resume::Time("2024-01-01T12:00:00Z".parse().unwrap()))
+        );
+    }
+}
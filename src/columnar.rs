@@ -0,0 +1,371 @@
+use arrow::array::RecordBatch;
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::StreamWriter;
+use arrow::json::reader::ReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::MetaData;
+use crate::projection::Projection;
+
+// Arrow schemas for the models, mirroring their serde field names so results can be
+// decoded straight from serialization. Timestamps are typed (UTC, milliseconds)
+// rather than left as RFC 3339 strings.
+
+fn utf8(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, true)
+}
+
+fn timestamp(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Millisecond, Some("+00:00".into())),
+        true,
+    )
+}
+
+fn list_of(name: &str, item: DataType) -> Field {
+    Field::new_list(name, Field::new_list_field(item, true), true)
+}
+
+fn struct_of(name: &str, fields: Vec<Field>) -> Field {
+    Field::new(name, DataType::Struct(Fields::from(fields)), true)
+}
+
+fn station_fields() -> Vec<Field> {
+    vec![
+        Field::new("stationUniqueId", DataType::Utf8, false),
+        Field::new("stationName", DataType::Utf8, false),
+        struct_of(
+            "fareZones",
+            vec![
+                utf8("raw"),
+                list_of("zones", DataType::UInt8),
+                Field::new("boundary", DataType::Boolean, true),
+            ],
+        ),
+        utf8("hubNaptanCode"),
+        Field::new("wifi", DataType::Boolean, true),
+        utf8("outsideStationUniqueId"),
+        Field::new("lat", DataType::Float64, true),
+        Field::new("lon", DataType::Float64, true),
+        list_of("lines", DataType::Utf8),
+    ]
+}
+
+fn route_section_fields() -> Vec<Field> {
+    vec![
+        utf8("id"),
+        utf8("lineId"),
+        utf8("routeCode"),
+        utf8("name"),
+        utf8("lineString"),
+        utf8("direction"),
+        utf8("originationName"),
+        utf8("destinationName"),
+        timestamp("validTo"),
+        timestamp("validFrom"),
+    ]
+}
+
+// StopPoint's own fields; its recursive `children` can't be expressed in a schema
+fn stop_point_fields() -> Vec<Field> {
+    vec![
+        utf8("naptanId"),
+        utf8("platformName"),
+        utf8("indicator"),
+        utf8("stopLetter"),
+        list_of("modes", DataType::Utf8),
+        utf8("icsCode"),
+        utf8("smsCode"),
+        utf8("stopType"),
+        utf8("stationNaptan"),
+        utf8("accessibilitySummary"),
+        utf8("hubNaptanCode"),
+        utf8("id"),
+        utf8("url"),
+        utf8("commonName"),
+        Field::new("distance", DataType::Float64, true),
+        utf8("placeType"),
+        Field::new("lat", DataType::Float64, true),
+        Field::new("lon", DataType::Float64, true),
+        Field::new("status", DataType::Boolean, true),
+    ]
+}
+
+fn station_ref(name: &str) -> Field {
+    struct_of(name, vec![utf8("stationName"), utf8("naptanId")])
+}
+
+// TrainPosition's variants flattened into one struct: `type` says which of the
+// other fields are set
+fn train_position(name: &str) -> Field {
+    struct_of(
+        name,
+        vec![
+            utf8("type"),
+            station_ref("station"),
+            utf8("platform"),
+            station_ref("from"),
+            station_ref("to"),
+            utf8("direction"),
+            utf8("text"),
+        ],
+    )
+}
+
+pub fn station_schema() -> SchemaRef {
+    Arc::new(Schema::new(station_fields()))
+}
+
+pub fn station_match_schema() -> SchemaRef {
+    let mut fields = vec![
+        Field::new("naptanId", DataType::Utf8, false),
+        Field::new("score", DataType::Float64, false),
+        Field::new("matchedOn", DataType::Utf8, false),
+    ];
+    fields.extend(station_fields());
+    Arc::new(Schema::new(fields))
+}
+
+pub fn nearby_station_schema() -> SchemaRef {
+    let mut fields = vec![Field::new("distance", DataType::Float64, false)];
+    fields.extend(station_fields());
+    Arc::new(Schema::new(fields))
+}
+
+pub fn station_point_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("uniqueId", DataType::Utf8, false),
+        Field::new("stationUniqueId", DataType::Utf8, false),
+        Field::new("areaName", DataType::Utf8, false),
+        Field::new("areaId", DataType::Int32, false),
+        Field::new("level", DataType::Int32, false),
+        Field::new("lat", DataType::Float64, false),
+        Field::new("lon", DataType::Float64, false),
+        Field::new("friendlyName", DataType::Utf8, false),
+    ]))
+}
+
+pub fn prediction_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        utf8("id"),
+        Field::new("operationType", DataType::Int32, true),
+        utf8("vehicleId"),
+        utf8("naptanId"),
+        utf8("stationName"),
+        utf8("lineId"),
+        utf8("lineName"),
+        list_of("lineIds", DataType::Utf8),
+        utf8("platformName"),
+        utf8("direction"),
+        utf8("bearing"),
+        utf8("destinationNaptanId"),
+        utf8("destinationName"),
+        timestamp("timestamp"),
+        Field::new("timeToStation", DataType::Int32, true),
+        utf8("currentLocation"),
+        utf8("towards"),
+        timestamp("expectedArrival"),
+        timestamp("timeToLive"),
+        utf8("modeName"),
+        struct_of("timing", vec![timestamp("read"), timestamp("sent")]),
+        utf8("stale"),
+        train_position("position"),
+    ]))
+}
+
+pub fn disruption_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        utf8("category"),
+        utf8("type"),
+        utf8("categoryDescription"),
+        utf8("description"),
+        utf8("summary"),
+        utf8("additionalInfo"),
+        timestamp("created"),
+        timestamp("lastUpdate"),
+        list_of(
+            "affectedRoutes",
+            DataType::Struct(Fields::from(route_section_fields())),
+        ),
+        list_of(
+            "affectedStops",
+            DataType::Struct(Fields::from(stop_point_fields())),
+        ),
+        utf8("closureText"),
+    ]))
+}
+
+pub fn lift_disruption_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        utf8("icsCode"),
+        utf8("naptanCode"),
+        utf8("stopPointName"),
+        utf8("outageStartArea"),
+        utf8("outageEndArea"),
+        utf8("message"),
+    ]))
+}
+
+// Decode results into a single batch, with the response context kept as schema metadata
+pub fn to_record_batch<T: Serialize>(
+    schema: SchemaRef,
+    context: &MetaData,
+    results: &[T],
+) -> Result<RecordBatch, ArrowError> {
+    let metadata = HashMap::from([
+        (
+            "request_time".to_string(),
+            context.request_time.to_rfc3339(),
+        ),
+        (
+            "response_time".to_string(),
+            context.response_time.to_rfc3339(),
+        ),
+        (
+            "response_latency".to_string(),
+            context.response_latency.to_string(),
+        ),
+        ("query".to_string(), context.query.clone()),
+    ]);
+    let schema = Arc::new(schema.as_ref().clone().with_metadata(metadata));
+
+    let mut decoder = ReaderBuilder::new(schema.clone())
+        .with_batch_size(results.len().max(1))
+        .build_decoder()?;
+    decoder.serialize(results)?;
+
+    Ok(decoder
+        .flush()?
+        .unwrap_or_else(|| RecordBatch::new_empty(schema)))
+}
+
+// Keep only the columns the projection selects. Columns are projected whole, so a
+// nested path such as `timing.read` keeps all of `timing`.
+pub fn project(batch: RecordBatch, projection: &Projection) -> Result<RecordBatch, ArrowError> {
+    if projection.is_empty() {
+        return Ok(batch);
+    }
+    let indices: Vec<usize> = batch
+        .schema()
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| projection.keeps(field.name()))
+        .map(|(i, _)| i)
+        .collect();
+    batch.project(&indices)
+}
+
+pub fn to_ipc_stream(batch: &RecordBatch) -> Result<Vec<u8>, ArrowError> {
+    let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema())?;
+    writer.write(batch)?;
+    writer.into_inner()
+}
+
+pub fn to_parquet(batch: &RecordBatch) -> Result<Vec<u8>, parquet::errors::ParquetError> {
+    let mut buffer = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buffer, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::models::Prediction;
+    use crate::routes::create_metadata;
+    use arrow::array::{Array, StringArray, StructArray, TimestampMillisecondArray};
+    use std::time::Instant;
+
+    #[test]
+    fn test_prediction_batch_has_typed_timestamps() {
+        let prediction: Prediction = serde_json::from_value(serde_json::json!({
+            "lineId": "victoria",
+            "expectedArrival": "2024-01-01T12:00:30Z",
+            "timeToStation": 30,
+            "timing": { "read": "2024-01-01T12:00:00Z" },
+        }))
+        .unwrap();
+        let context = create_metadata(Instant::now(), "victoria");
+
+        let batch = to_record_batch(prediction_schema(), &context, &[prediction]).unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.schema().metadata()["query"], "victoria");
+
+        let expected = batch
+            .column_by_name("expectedArrival")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert_eq!(expected.value(0), 1_704_110_430_000);
+        assert!(to_ipc_stream(&batch).is_ok());
+    }
+
+    #[test]
+    fn test_prediction_batch_keeps_positions() {
+        let predictions: Vec<Prediction> = serde_json::from_value(serde_json::json!([
+            {
+                "lineId": "victoria",
+                "position": {
+                    "type": "between",
+                    "from": { "stationName": "Oval", "naptanId": "940GZZLUOVL" },
+                    "to": { "stationName": "Stockwell", "naptanId": null },
+                },
+            },
+            { "lineId": "victoria" },
+        ]))
+        .unwrap();
+        let context = create_metadata(Instant::now(), "victoria");
+
+        let batch = to_record_batch(prediction_schema(), &context, &predictions).unwrap();
+        let position = batch
+            .column_by_name("position")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        assert!(position.is_null(1));
+        let kind = position
+            .column_by_name("type")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(kind.value(0), "between");
+        let from = position
+            .column_by_name("from")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StructArray>()
+            .unwrap();
+        let naptan_id = from
+            .column_by_name("naptanId")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(naptan_id.value(0), "940GZZLUOVL");
+        assert!(to_parquet(&batch).is_ok());
+    }
+
+    #[test]
+    fn test_station_parquet() {
+        let dataset = Dataset::load();
+        let context = create_metadata(Instant::now(), "stations");
+
+        let batch = to_record_batch(station_schema(), &context, &dataset.stations).unwrap();
+        assert_eq!(batch.num_rows(), dataset.stations.len());
+        assert!(batch.column_by_name("fareZones").unwrap().null_count() == 0);
+
+        let parquet = to_parquet(&batch).unwrap();
+        assert_eq!(&parquet[..4], b"PAR1");
+    }
+}
@@ -0,0 +1,275 @@
+use serde_json::json;
+use tracing::info;
+
+use crate::fare_zones::FareZones;
+use crate::geo::StationIndex;
+use crate::models::{Station, StationPoint};
+use crate::search::StationSearch;
+
+pub const TUBE_LINES: &[&str] = &[
+    "bakerloo",
+    "central",
+    "circle",
+    "district",
+    "hammersmith-city",
+    "jubilee",
+    "metropolitan",
+    "northern",
+    "piccadilly",
+    "victoria",
+    "waterloo-city",
+];
+
+const OVERGROUND_LINES: &[&str] = &[
+    "overground",
+    "london-overground",
+    "liberty",
+    "lioness",
+    "mildmay",
+    "suffragette",
+    "weaver",
+    "windrush",
+];
+
+// TfL mode name for a line id, for the rail modes that appear in the station data
+pub fn line_mode(line_id: &str) -> Option<&'static str> {
+    match line_id {
+        "dlr" => Some("dlr"),
+        "elizabeth" => Some("elizabeth-line"),
+        "tram" => Some("tram"),
+        id if TUBE_LINES.contains(&id) => Some("tube"),
+        id if OVERGROUND_LINES.contains(&id) => Some("overground"),
+        _ => None,
+    }
+}
+
+// In-memory station dataset, loaded once at startup and shared between routers.
+// In a real implementation this would be loaded from the TfL station data files,
+// for now we build it from sample data.
+pub struct Dataset {
+    pub stations: Vec<Station>,
+    pub station_points: Vec<StationPoint>,
+    pub platforms: Vec<serde_json::Value>,
+    pub search: StationSearch,
+    pub spatial: StationIndex,
+}
+
+impl Dataset {
+    pub fn load() -> Self {
+        let stations = sample_stations();
+        let station_points = sample_station_points();
+        let platforms = sample_platforms();
+        let search = StationSearch::new(&stations, crate::search::load_aliases());
+        let spatial = StationIndex::new(&stations, &station_points);
+
+        info!(
+            "Loaded dataset with {} stations, {} station points",
+            stations.len(),
+            station_points.len()
+        );
+
+        Self {
+            stations,
+            station_points,
+            platforms,
+            search,
+            spatial,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn station(
+    station_unique_id: &str,
+    station_name: &str,
+    fare_zones: &str,
+    hub_naptan_code: Option<&str>,
+    outside_station_unique_id: &str,
+    lat: f64,
+    lon: f64,
+    lines: &[&str],
+) -> Station {
+    Station {
+        station_unique_id: station_unique_id.to_string(),
+        station_name: station_name.to_string(),
+        fare_zones: Some(FareZones::from(fare_zones)),
+        hub_naptan_code: hub_naptan_code.map(str::to_string),
+        wifi: Some(true),
+        outside_station_unique_id: Some(outside_station_unique_id.to_string()),
+        lat: Some(lat),
+        lon: Some(lon),
+        lines: Some(lines.iter().map(|line| line.to_string()).collect()),
+    }
+}
+
+fn sample_stations() -> Vec<Station> {
+    vec![
+        station(
+            "940GZZLUASL",
+            "Arsenal",
+            "2",
+            None,
+            "490G000ASL",
+            51.5586,
+            -0.1059,
+            &["piccadilly"],
+        ),
+        station(
+            "940GZZLUBKG",
+            "Barking",
+            "4",
+            None,
+            "490G000BKG",
+            51.5396,
+            0.0813,
+            &["district", "hammersmith-city", "overground"],
+        ),
+        station(
+            "940GZZLUBXN",
+            "Brixton",
+            "2",
+            None,
+            "490G000BXN",
+            51.4627,
+            -0.1145,
+            &["victoria"],
+        ),
+        station(
+            "940GZZLUKSX",
+            "King's Cross St. Pancras",
+            "1",
+            Some("HUBKGX"),
+            "490G000KSX",
+            51.5308,
+            -0.1238,
+            &[
+                "circle",
+                "hammersmith-city",
+                "metropolitan",
+                "northern",
+                "piccadilly",
+                "victoria",
+            ],
+        ),
+        station(
+            "940GZZLUOVL",
+            "Oval",
+            "2",
+            None,
+            "490G000OVL",
+            51.4819,
+            -0.1127,
+            &["northern"],
+        ),
+        station(
+            "940GZZLUSBC",
+            "Shepherd's Bush",
+            "2",
+            Some("HUBSPB"),
+            "490G000SBC",
+            51.5046,
+            -0.2187,
+            &["central"],
+        ),
+        station(
+            "940GZZLUSBM",
+            "Shepherd's Bush Market",
+            "2",
+            None,
+            "490G000SBM",
+            51.5058,
+            -0.2265,
+            &["circle", "hammersmith-city"],
+        ),
+        station(
+            "940GZZLUSKW",
+            "Stockwell",
+            "2",
+            None,
+            "490G000SKW",
+            51.4723,
+            -0.1228,
+            &["northern", "victoria"],
+        ),
+        station(
+            "940GZZLUSTD",
+            "Stratford",
+            "2/3",
+            Some("HUBSRA"),
+            "490G000STD",
+            51.5419,
+            -0.0033,
+            &["central", "jubilee", "elizabeth", "dlr", "mildmay"],
+        ),
+        station(
+            "940GZZLUVIC",
+            "Victoria",
+            "1",
+            Some("HUBVIC"),
+            "490G000VIC",
+            51.4965,
+            -0.1447,
+            &["circle", "district", "victoria"],
+        ),
+    ]
+}
+
+fn sample_station_points() -> Vec<StationPoint> {
+    vec![
+        StationPoint {
+            unique_id: "ASL-1".to_string(),
+            station_unique_id: "940GZZLUASL".to_string(),
+            area_name: "Arsenal Station".to_string(),
+            area_id: 1,
+            level: 0,
+            lat: 51.5586,
+            lon: -0.1059,
+            friendly_name: "Arsenal Station Entrance".to_string(),
+        },
+        StationPoint {
+            unique_id: "ASL-2".to_string(),
+            station_unique_id: "940GZZLUASL".to_string(),
+            area_name: "Arsenal Station Platform".to_string(),
+            area_id: 2,
+            level: -1,
+            lat: 51.5587,
+            lon: -0.1060,
+            friendly_name: "Arsenal Station Platform".to_string(),
+        },
+        StationPoint {
+            unique_id: "BKG-1".to_string(),
+            station_unique_id: "940GZZLUBKG".to_string(),
+            area_name: "Barking Station".to_string(),
+            area_id: 3,
+            level: 0,
+            lat: 51.5396,
+            lon: 0.0813,
+            friendly_name: "Barking Station Entrance".to_string(),
+        },
+    ]
+}
+
+fn sample_platforms() -> Vec<serde_json::Value> {
+    vec![
+        json!({
+            "PlatformUniqueId": "ASL-P1",
+            "StationUniqueId": "940GZZLUASL",
+            "PlatformNumber": "1",
+            "CardinalDirection": "NB",
+            "PlatformNaptanCode": "940GZZLUASL1",
+            "PlatformFriendlyName": "Northbound Platform 1",
+            "IsCustomerFacing": true,
+            "HasServiceInterchange": false
+        }),
+        json!({
+            "PlatformUniqueId": "ASL-P2",
+            "StationUniqueId": "940GZZLUASL",
+            "PlatformNumber": "2",
+            "CardinalDirection": "SB",
+            "PlatformNaptanCode": "940GZZLUASL2",
+            "PlatformFriendlyName": "Southbound Platform 2",
+            "IsCustomerFacing": true,
+            "HasServiceInterchange": false
+        }),
+    ]
+}
@@ -21,6 +21,16 @@ pub enum AppError {
     #[error("Not found: {0}")]
     NotFound(String),
 
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[allow(dead_code)]
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+
     #[error("Deserialization error at path '{path}': {message}")]
     DeserializationError {
         path: String,
@@ -36,6 +46,9 @@ impl IntoResponse for AppError {
             AppError::ParseError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err, None),
             AppError::InternalError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err, None),
             AppError::NotFound(err) => (StatusCode::NOT_FOUND, err, None),
+            AppError::Unauthorized(err) => (StatusCode::UNAUTHORIZED, err, None),
+            AppError::Conflict(err) => (StatusCode::CONFLICT, err, None),
+            AppError::NotAcceptable(err) => (StatusCode::NOT_ACCEPTABLE, err, None),
             AppError::DeserializationError {
                 path,
                 message,
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeSet;
+
+// Structured fare zones, parsed from TfL's free-form zone strings such as "1", "2/3"
+// or "2+3". A station listed in more than one zone is a boundary station, and counts
+// as being in each of them (it is charged as whichever zone is cheaper for the journey).
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FareZones {
+    pub raw: String,
+    pub zones: BTreeSet<u8>,
+    pub boundary: bool,
+}
+
+impl FareZones {
+    pub fn contains_any(&self, zones: &BTreeSet<u8>) -> bool {
+        !self.zones.is_disjoint(zones)
+    }
+}
+
+impl From<&str> for FareZones {
+    // Lenient: tokens that aren't zone numbers (e.g. "Outside") are kept in `raw` only
+    fn from(raw: &str) -> Self {
+        let zones: BTreeSet<u8> = tokens(raw)
+            .filter_map(|token| parse_token(token).ok())
+            .flatten()
+            .collect();
+
+        Self {
+            raw: raw.to_string(),
+            boundary: zones.len() > 1,
+            zones,
+        }
+    }
+}
+
+// Accept either TfL's raw string or our own structured form, so serialized
+// stations can be read back in
+impl<'de> Deserialize<'de> for FareZones {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Raw(String),
+            Structured { raw: String },
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Raw(raw) | Repr::Structured { raw } => FareZones::from(raw.as_str()),
+        })
+    }
+}
+
+// Strict parse of a zone filter such as "1", "1-2" or "1,3-4"
+pub fn parse_zone_filter(filter: &str) -> Result<BTreeSet<u8>, String> {
+    let mut zones = BTreeSet::new();
+    for token in tokens(filter) {
+        zones.extend(parse_token(token)?);
+    }
+    if zones.is_empty() {
+        return Err(format!("No zones in filter '{}'", filter));
+    }
+    Ok(zones)
+}
+
+fn tokens(text: &str) -> impl Iterator<Item = &str> {
+    text.split(['/', '+', '|', ',', '&', ' '])
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+// A single zone number or an inclusive range like "1-3"
+fn parse_token(token: &str) -> Result<BTreeSet<u8>, String> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<u8>()
+            .map_err(|_| format!("Invalid zone '{}'", token))
+    };
+
+    match token.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (parse(from)?, parse(to)?);
+            if from > to {
+                return Err(format!("Invalid zone range '{}'", token));
+            }
+            Ok((from..=to).collect())
+        }
+        None => Ok(BTreeSet::from([parse(token)?])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fare_zones() {
+        let zones = FareZones::from("2/3");
+        assert_eq!(zones.zones, BTreeSet::from([2, 3]));
+        assert!(zones.boundary);
+
+        let zones = FareZones::from("1");
+        assert_eq!(zones.zones, BTreeSet::from([1]));
+        assert!(!zones.boundary);
+
+        let zones = FareZones::from("Outside");
+        assert!(zones.zones.is_empty());
+        assert_eq!(zones.raw, "Outside");
+    }
+
+    #[test]
+    fn test_parse_zone_filter() {
+        assert_eq!(parse_zone_filter("1-2").unwrap(), BTreeSet::from([1, 2]));
+        assert_eq!(
+            parse_zone_filter("1,3-4").unwrap(),
+            BTreeSet::from([1, 3, 4])
+        );
+        assert!(parse_zone_filter("2-1").is_err());
+        assert!(parse_zone_filter("zone one").is_err());
+    }
+}
@@ -0,0 +1,1055 @@
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, Query},
+    http::{
+        header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        request::Parts,
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response as AxumResponse},
+    Json,
+};
+use chrono::Utc;
+use futures::{
+    future::{ready, try_join_all},
+    stream::{self, FuturesUnordered, StreamExt},
+    Future,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::time::Instant;
+
+#[cfg(feature = "arrow")]
+use arrow::datatypes::SchemaRef;
+
+use crate::error::{AppError, AppResult};
+use crate::geo::Geometry;
+use crate::models::{
+    Disruption, DisruptionChange, LiftDisruption, MetaData, ModeArrivals, NearbyStation,
+    PlatformBoard, Prediction, PredictionAccuracy, Response, Station, StationAccessibility,
+    StationHeadways, StationMatch, StationPoint, TrainLocation,
+};
+use crate::pagination::Pagination;
+use crate::projection::Projection;
+use crate::routes::{create_metadata, create_response};
+use crate::webhooks::{DeadLetter, Webhook};
+
+// Output formats a Response<T> can be rendered as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Csv,
+    Ndjson,
+    GeoJson,
+    Xml,
+    Text,
+    Html,
+    #[cfg(feature = "arrow")]
+    Arrow,
+    #[cfg(feature = "arrow")]
+    Parquet,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            "ndjson" => Some(Self::Ndjson),
+            "geojson" => Some(Self::GeoJson),
+            "xml" | "siri" => Some(Self::Xml),
+            "text" | "txt" => Some(Self::Text),
+            "html" => Some(Self::Html),
+            #[cfg(feature = "arrow")]
+            "arrow" => Some(Self::Arrow),
+            #[cfg(feature = "arrow")]
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            "text/csv" => Some(Self::Csv),
+            "application/x-ndjson" => Some(Self::Ndjson),
+            GEOJSON_CONTENT_TYPE => Some(Self::GeoJson),
+            "application/xml" | "text/xml" => Some(Self::Xml),
+            // Not text/html: browsers ask for it first everywhere, and should get
+            // JSON unless they ask with `?format=html`
+            "text/plain" => Some(Self::Text),
+            #[cfg(feature = "arrow")]
+            ARROW_CONTENT_TYPE => Some(Self::Arrow),
+            #[cfg(feature = "arrow")]
+            PARQUET_CONTENT_TYPE | "application/x-parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+
+    // Pick the most preferred supported format from an Accept header, honouring
+    // q-values. Unsupported or missing Accept headers fall back to JSON.
+    fn from_accept(accept: &str) -> Self {
+        let mut candidates: Vec<(&str, f32)> = accept
+            .split(',')
+            .map(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let media_type = parts.next().unwrap_or_default();
+                let q = parts
+                    .find_map(|p| p.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                (media_type, q)
+            })
+            .filter(|(_, q)| *q > 0.0)
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        candidates
+            .into_iter()
+            .find_map(|(media_type, _)| Self::from_media_type(media_type))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FormatQuery {
+    format: Option<String>,
+    fields: Option<String>,
+    exclude: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+// How the client asked for results to be rendered, from `?format=` or, failing
+// that, the Accept header, which of their fields to include (`?fields=` and
+// `?exclude=`), and how to sort and page them, along with any If-None-Match
+// validators. Handlers take this as an extractor and pass their Response<T>
+// through `respond`.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseOptions {
+    pub format: OutputFormat,
+    pub projection: Projection,
+    pub pagination: Pagination,
+    pub if_none_match: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ResponseOptions
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<FormatQuery>::try_from_uri(&parts.uri)
+            .map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        let format = match query.format {
+            Some(name) => OutputFormat::from_name(&name)
+                .ok_or_else(|| AppError::ParseError(format!("Unsupported format: {}", name)))?,
+            None => parts
+                .headers
+                .get(ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .map(OutputFormat::from_accept)
+                .unwrap_or_default(),
+        };
+
+        let projection = Projection::new(query.fields.as_deref(), query.exclude.as_deref());
+
+        let pagination = Pagination::new(
+            query.sort.as_deref(),
+            query.order.as_deref(),
+            query.offset,
+            query.limit,
+            query.cursor.as_deref(),
+        )?;
+
+        let if_none_match = parts
+            .headers
+            .get(IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        Ok(Self {
+            format,
+            projection,
+            pagination,
+            if_none_match,
+        })
+    }
+}
+
+impl ResponseOptions {
+    pub fn respond<T: Resource>(&self, response: Response<T>) -> Formatted<T> {
+        Formatted::Complete {
+            options: self.clone(),
+            response: self.pagination.apply(response, T::DEFAULT_SORT),
+        }
+    }
+
+    // Whether `respond_fan_out` will stream rather than collect the results first
+    pub fn streams(&self) -> bool {
+        self.format == OutputFormat::Ndjson && self.pagination.is_empty()
+    }
+
+    // Respond with the combined results of several upstream requests. NDJSON is
+    // streamed, writing each request's results as soon as it completes (in arrival
+    // order, so only when no sorting or paging was asked for); other formats wait
+    // for all of them and respond as usual.
+    pub async fn respond_fan_out<T, F>(
+        &self,
+        start_time: Instant,
+        query: &str,
+        fetches: Vec<F>,
+    ) -> AppResult<Formatted<T>>
+    where
+        T: Resource + Send + 'static,
+        F: Future<Output = AppResult<Vec<T>>> + Send + 'static,
+    {
+        if !self.streams() {
+            let results = try_join_all(fetches).await?.into_iter().flatten().collect();
+            return Ok(self.respond(create_response(start_time, query, results)));
+        }
+
+        let context = stream::once(ready(ndjson_line(&create_metadata(start_time, query))));
+        let projection = self.projection.clone();
+        let results = fetches
+            .into_iter()
+            .collect::<FuturesUnordered<_>>()
+            .flat_map(move |fetched| match fetched {
+                Ok(results) => stream::iter(
+                    results
+                        .iter()
+                        .map(|result| ndjson_line(&project(&projection, result)))
+                        .collect::<Vec<_>>(),
+                ),
+                // Too late to change the status code, so report the failure in-band
+                Err(e) => stream::iter(vec![ndjson_line(&json!({
+                    "success": false,
+                    "error": e.to_string(),
+                }))]),
+            });
+
+        Ok(Formatted::Streaming(Body::from_stream(
+            context.chain(results).map(Ok::<_, Infallible>),
+        )))
+    }
+}
+
+// Per-type hooks for the output formats that need more than serde provides.
+// Every result type served through `Formatted` implements this.
+pub trait Resource: Serialize {
+    // Serialized field results are sorted by when no `sort=` is given
+    const DEFAULT_SORT: Option<&'static str> = None;
+
+    // How long clients may cache a response, in seconds
+    fn max_age(_results: &[Self]) -> u64
+    where
+        Self: Sized,
+    {
+        DEFAULT_MAX_AGE
+    }
+
+    // Whether results carry geometry, and so can be rendered as GeoJSON
+    const GEOGRAPHIC: bool = false;
+
+    fn geometry(&self) -> Option<Geometry> {
+        None
+    }
+
+    // The GeoJSON features for a result, given its projected fields: by default a
+    // single feature located by `geometry()`
+    fn features(&self, properties: Value) -> Vec<Value> {
+        vec![feature(self.geometry(), properties)]
+    }
+
+    // SIRI XML document for a list of results, if the type has a SIRI rendering
+    fn siri(_context: &MetaData, _results: &[Self]) -> Option<AppResult<Vec<u8>>>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    // Plain-text and HTML renderings for displays, if the type has them
+    fn text(_context: &MetaData, _results: &[Self]) -> Option<String>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    fn html(_context: &MetaData, _results: &[Self]) -> Option<String>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    // Arrow schema matching the type's serialized fields, if it has one
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        None
+    }
+}
+
+impl Resource for Station {
+    const GEOGRAPHIC: bool = true;
+
+    fn max_age(_results: &[Self]) -> u64 {
+        STATIC_MAX_AGE
+    }
+
+    fn geometry(&self) -> Option<Geometry> {
+        Geometry::point(self.lat, self.lon)
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::station_schema())
+    }
+}
+
+impl Resource for StationMatch {
+    const GEOGRAPHIC: bool = true;
+
+    fn max_age(_results: &[Self]) -> u64 {
+        STATIC_MAX_AGE
+    }
+
+    fn geometry(&self) -> Option<Geometry> {
+        self.station.geometry()
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::station_match_schema())
+    }
+}
+
+impl Resource for NearbyStation {
+    const GEOGRAPHIC: bool = true;
+
+    fn max_age(_results: &[Self]) -> u64 {
+        STATIC_MAX_AGE
+    }
+
+    fn geometry(&self) -> Option<Geometry> {
+        self.station.geometry()
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::nearby_station_schema())
+    }
+}
+
+impl Resource for StationPoint {
+    const GEOGRAPHIC: bool = true;
+
+    fn max_age(_results: &[Self]) -> u64 {
+        STATIC_MAX_AGE
+    }
+
+    fn geometry(&self) -> Option<Geometry> {
+        Geometry::point(Some(self.lat), Some(self.lon))
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::station_point_schema())
+    }
+}
+
+impl Resource for Prediction {
+    const DEFAULT_SORT: Option<&'static str> = Some("timeToStation");
+
+    // Until the first prediction expires, if that's sooner than the default
+    fn max_age(results: &[Self]) -> u64 {
+        let now = Utc::now();
+        results
+            .iter()
+            .filter_map(|prediction| prediction.time_to_live)
+            .map(|expiry| (expiry - now).num_seconds().max(0) as u64)
+            .min()
+            .unwrap_or(DEFAULT_MAX_AGE)
+            .min(DEFAULT_MAX_AGE)
+    }
+
+    fn siri(context: &MetaData, results: &[Self]) -> Option<AppResult<Vec<u8>>> {
+        Some(crate::siri::estimated_timetable(context, results))
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::prediction_schema())
+    }
+}
+
+impl Resource for Disruption {
+    const GEOGRAPHIC: bool = true;
+
+    fn features(&self, properties: Value) -> Vec<Value> {
+        disruption_features(self, properties)
+    }
+
+    fn siri(context: &MetaData, results: &[Self]) -> Option<AppResult<Vec<u8>>> {
+        Some(crate::siri::situation_exchange(context, results))
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::disruption_schema())
+    }
+}
+
+impl Resource for DisruptionChange {
+    const DEFAULT_SORT: Option<&'static str> = Some("sequence");
+    const GEOGRAPHIC: bool = true;
+
+    // The disruption's features, with the change's fields around their properties
+    fn features(&self, mut properties: Value) -> Vec<Value> {
+        let disruption = properties
+            .as_object_mut()
+            .and_then(|fields| fields.remove("disruption"));
+        let projected = disruption.is_some();
+
+        disruption_features(&self.disruption, disruption.unwrap_or_else(|| json!({})))
+            .into_iter()
+            .map(|mut feature| {
+                let mut properties = properties.clone();
+                if let (Some(fields), true) = (properties.as_object_mut(), projected) {
+                    fields.insert("disruption".to_string(), feature["properties"].take());
+                }
+                feature["properties"] = properties;
+                feature
+            })
+            .collect()
+    }
+}
+
+impl Resource for LiftDisruption {
+    #[cfg(feature = "arrow")]
+    fn arrow_schema() -> Option<SchemaRef> {
+        Some(crate::columnar::lift_disruption_schema())
+    }
+}
+
+impl Resource for StationAccessibility {}
+impl Resource for Webhook {}
+impl Resource for DeadLetter {}
+impl Resource for ModeArrivals {}
+
+impl Resource for TrainLocation {
+    const GEOGRAPHIC: bool = true;
+
+    fn geometry(&self) -> Option<Geometry> {
+        Geometry::point(self.lat, self.lon)
+    }
+}
+
+impl Resource for StationHeadways {}
+impl Resource for PredictionAccuracy {}
+
+impl Resource for PlatformBoard {
+    fn text(context: &MetaData, results: &[Self]) -> Option<String> {
+        Some(crate::board::to_text(context, results))
+    }
+
+    fn html(context: &MetaData, results: &[Self]) -> Option<String> {
+        Some(crate::board::to_html(context, results))
+    }
+}
+impl Resource for Value {}
+
+// A Response<T> paired with the format it should be rendered in, or an already
+// formatted body that is still being streamed. Built once per request and consumed
+// straight away, so the size difference between variants doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum Formatted<T> {
+    Complete {
+        options: ResponseOptions,
+        response: Response<T>,
+    },
+    Streaming(Body),
+}
+
+impl<T: Resource> IntoResponse for Formatted<T> {
+    fn into_response(self) -> AxumResponse {
+        match self {
+            Formatted::Complete { options, response } => {
+                render(&options, response).unwrap_or_else(IntoResponse::into_response)
+            }
+            Formatted::Streaming(body) => {
+                ([(CONTENT_TYPE, NDJSON_CONTENT_TYPE)], body).into_response()
+            }
+        }
+    }
+}
+
+fn render<T: Resource>(
+    options: &ResponseOptions,
+    response: Response<T>,
+) -> AppResult<AxumResponse> {
+    let ResponseOptions {
+        format,
+        projection,
+        if_none_match,
+        ..
+    } = options;
+
+    // Conditional GET: the ETag covers the results as rendered, but not the context
+    let etag = entity_tag(*format, projection, &response)?;
+    let cache_control = format!("public, max-age={}", T::max_age(&response.results));
+    let validators = [
+        (ETAG, HeaderValue::from_str(&etag)),
+        (CACHE_CONTROL, HeaderValue::from_str(&cache_control)),
+    ]
+    .map(|(name, value)| (name, value.expect("ETag and Cache-Control are ASCII")));
+    if if_none_match
+        .as_deref()
+        .is_some_and(|header| etag_matches(header, &etag))
+    {
+        return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+    }
+
+    let mut headers = metadata_headers(&response.context);
+    headers.extend(validators.clone());
+    headers.insert(
+        HeaderName::from_static("x-total-count"),
+        response.total.into(),
+    );
+    if let Some(cursor) = response
+        .next_cursor
+        .as_deref()
+        .and_then(|cursor| HeaderValue::from_str(cursor).ok())
+    {
+        headers.insert(HeaderName::from_static("x-next-cursor"), cursor);
+    }
+    let (content_type, body): (&str, Body) = match format {
+        OutputFormat::Json if projection.is_empty() => {
+            return Ok((validators, Json(response)).into_response())
+        }
+        OutputFormat::Json => {
+            let response = Response {
+                results: project_all(projection, &response.results),
+                context: response.context,
+                success: response.success,
+                total: response.total,
+                next_cursor: response.next_cursor,
+            };
+            return Ok((validators, Json(response)).into_response());
+        }
+        OutputFormat::Ndjson => {
+            let mut body = ndjson_line(&response.context);
+            for result in &response.results {
+                body.push_str(&ndjson_line(&project(projection, result)));
+            }
+            (NDJSON_CONTENT_TYPE, body.into())
+        }
+        OutputFormat::GeoJson => {
+            if !T::GEOGRAPHIC {
+                return Err(AppError::NotAcceptable(
+                    "GeoJSON output is not available here".to_string(),
+                ));
+            }
+            let body = serde_json::to_vec(&to_geojson(&response.results, projection)?)
+                .map_err(|e| AppError::InternalError(format!("Failed to write GeoJSON: {}", e)))?;
+            (GEOJSON_CONTENT_TYPE, body.into())
+        }
+        OutputFormat::Xml => {
+            let body = T::siri(&response.context, &response.results).ok_or_else(|| {
+                AppError::NotAcceptable("SIRI XML output is not available here".to_string())
+            })??;
+            (XML_CONTENT_TYPE, body.into())
+        }
+        OutputFormat::Text => {
+            let body = T::text(&response.context, &response.results).ok_or_else(|| {
+                AppError::NotAcceptable("Plain-text output is not available here".to_string())
+            })?;
+            ("text/plain; charset=utf-8", body.into())
+        }
+        OutputFormat::Html => {
+            let body = T::html(&response.context, &response.results).ok_or_else(|| {
+                AppError::NotAcceptable("HTML output is not available here".to_string())
+            })?;
+            ("text/html; charset=utf-8", body.into())
+        }
+        OutputFormat::Csv => {
+            let body = to_csv(&project_all(projection, &response.results))
+                .map_err(|e| AppError::InternalError(format!("Failed to write CSV: {}", e)))?;
+            ("text/csv; charset=utf-8", body.into())
+        }
+        #[cfg(feature = "arrow")]
+        OutputFormat::Arrow | OutputFormat::Parquet => {
+            let schema = T::arrow_schema().ok_or_else(|| {
+                AppError::NotAcceptable("Columnar output is not available here".to_string())
+            })?;
+            let batch =
+                crate::columnar::to_record_batch(schema, &response.context, &response.results)
+                    .and_then(|batch| crate::columnar::project(batch, projection))
+                    .map_err(|e| {
+                        AppError::InternalError(format!("Failed to build batch: {}", e))
+                    })?;
+            if *format == OutputFormat::Arrow {
+                let body = crate::columnar::to_ipc_stream(&batch).map_err(|e| {
+                    AppError::InternalError(format!("Failed to write Arrow: {}", e))
+                })?;
+                (ARROW_CONTENT_TYPE, body.into())
+            } else {
+                let body = crate::columnar::to_parquet(&batch).map_err(|e| {
+                    AppError::InternalError(format!("Failed to write Parquet: {}", e))
+                })?;
+                (PARQUET_CONTENT_TYPE, body.into())
+            }
+        }
+    };
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    Ok((headers, body).into_response())
+}
+
+// Cache lifetimes: live data is refreshed upstream about every 30 seconds, while the
+// station dataset only changes on restart
+const DEFAULT_MAX_AGE: u64 = 30;
+const STATIC_MAX_AGE: u64 = 3600;
+
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+const GEOJSON_CONTENT_TYPE: &str = "application/geo+json";
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+#[cfg(feature = "arrow")]
+const ARROW_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+#[cfg(feature = "arrow")]
+const PARQUET_CONTENT_TYPE: &str = "application/vnd.apache.parquet";
+
+// ETag over the serialized (projected) results and paging, in a given format. It's
+// weak: the body is compressed afterwards in whichever encoding the client accepts,
+// and a strong tag would have to differ between encodings.
+fn entity_tag<T: Serialize>(
+    format: OutputFormat,
+    projection: &Projection,
+    response: &Response<T>,
+) -> AppResult<String> {
+    let results = serde_json::to_vec(&project_all(projection, &response.results))
+        .map_err(|e| AppError::InternalError(format!("Failed to serialize results: {}", e)))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}\n", format));
+    hasher.update(&results);
+    hasher.update(format!("\n{}\n{:?}", response.total, response.next_cursor));
+    let digest: String = hasher.finalize()[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(format!("W/\"{}\"", digest))
+}
+
+// If-None-Match uses weak comparison, and may list several tags or `*`
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// A result as JSON with the requested projection applied
+fn project<T: Serialize>(projection: &Projection, result: &T) -> Value {
+    let value = serde_json::to_value(result).unwrap_or_else(
+        |e| json!({ "success": false, "error": format!("Failed to serialize result: {}", e) }),
+    );
+    projection.apply(value)
+}
+
+fn project_all<T: Serialize>(projection: &Projection, results: &[T]) -> Vec<Value> {
+    results
+        .iter()
+        .map(|result| project(projection, result))
+        .collect()
+}
+
+// One value serialized as a line of newline-delimited JSON
+fn ndjson_line<V: Serialize>(value: &V) -> String {
+    let mut line = serde_json::to_string(value).unwrap_or_else(|e| {
+        json!({ "success": false, "error": format!("Failed to serialize result: {}", e) })
+            .to_string()
+    });
+    line.push('\n');
+    line
+}
+
+// Fields that make up a result's geometry, so aren't repeated in its properties
+const GEOMETRY_FIELDS: &[&str] = &["lat", "lon", "lineString"];
+
+fn without_geometry(mut properties: Value) -> Value {
+    if let Value::Object(fields) = &mut properties {
+        fields.retain(|key, _| !GEOMETRY_FIELDS.contains(&key.as_str()));
+    }
+    properties
+}
+
+fn feature(geometry: Option<Geometry>, properties: Value) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": without_geometry(properties),
+    })
+}
+
+// A LineString feature per affected route section and a Point feature per affected
+// stop, each with the disruption's other fields plus the section (`affectedRoute`)
+// or stop (`affectedStop`) it locates. A disruption with neither gets a single
+// feature with null geometry.
+fn disruption_features(disruption: &Disruption, properties: Value) -> Vec<Value> {
+    let Value::Object(mut fields) = properties else {
+        return vec![feature(None, properties)];
+    };
+    let routes = fields.remove("affectedRoutes");
+    let stops = fields.remove("affectedStops");
+    let projected = |list: &Option<Value>, index: usize| {
+        list.as_ref()
+            .and_then(|list| list.get(index))
+            .cloned()
+            .map(without_geometry)
+    };
+
+    let sections = disruption
+        .affected_routes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, route)| {
+            Some((
+                route.geometry()?,
+                "affectedRoute",
+                projected(&routes, index),
+            ))
+        });
+    let points = disruption
+        .affected_stops
+        .iter()
+        .enumerate()
+        .filter_map(|(index, stop)| {
+            Some((
+                Geometry::point(stop.lat, stop.lon)?,
+                "affectedStop",
+                projected(&stops, index),
+            ))
+        });
+    let features: Vec<Value> = sections
+        .chain(points)
+        .map(|(geometry, key, located)| {
+            let mut fields = fields.clone();
+            if let Some(located) = located {
+                fields.insert(key.to_string(), located);
+            }
+            feature(Some(geometry), Value::Object(fields))
+        })
+        .collect();
+    if !features.is_empty() {
+        return features;
+    }
+
+    let strip_all = |list: Value| match list {
+        Value::Array(items) => Value::Array(items.into_iter().map(without_geometry).collect()),
+        other => other,
+    };
+    if let Some(routes) = routes {
+        fields.insert("affectedRoutes".to_string(), strip_all(routes));
+    }
+    if let Some(stops) = stops {
+        fields.insert("affectedStops".to_string(), strip_all(stops));
+    }
+    vec![feature(None, Value::Object(fields))]
+}
+
+// A FeatureCollection of each result's features, with its other fields as
+// properties
+fn to_geojson<T: Resource>(results: &[T], projection: &Projection) -> AppResult<Value> {
+    let mut features = Vec::new();
+    for result in results {
+        let properties = projection.apply(
+            serde_json::to_value(result).map_err(|e| AppError::InternalError(e.to_string()))?,
+        );
+        features.extend(result.features(properties));
+    }
+
+    Ok(json!({
+        "type": "FeatureCollection",
+        "features": features,
+    }))
+}
+
+// The MetaData context as response headers, for formats with nowhere else to put it
+fn metadata_headers(context: &MetaData) -> HeaderMap {
+    let values = [
+        ("x-request-time", context.request_time.to_rfc3339()),
+        ("x-response-time", context.response_time.to_rfc3339()),
+        ("x-response-latency", context.response_latency.to_string()),
+        ("x-query", context.query.clone()),
+    ];
+    let dropped = context
+        .freshness
+        .as_ref()
+        .map(|freshness| ("x-stale-predictions-dropped", freshness.dropped.to_string()));
+    let gap = context.gap.map(|gap| ("x-changes-gap", gap.to_string()));
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in values.into_iter().chain(dropped).chain(gap) {
+        // Skip values that aren't valid in a header (e.g. non-ASCII queries)
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+    headers
+}
+
+// Flatten a JSON value into dotted column names. Objects and arrays of objects are
+// expanded (`timing.read`, `lineStatuses.0.reason`), arrays of scalars are joined.
+fn flatten(prefix: &str, value: &Value, row: &mut Map<String, Value>) {
+    let key = |k: &str| {
+        if prefix.is_empty() {
+            k.to_string()
+        } else {
+            format!("{}.{}", prefix, k)
+        }
+    };
+
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                flatten(&key(k), v, row);
+            }
+        }
+        Value::Array(items) if items.iter().any(|v| v.is_object() || v.is_array()) => {
+            for (i, v) in items.iter().enumerate() {
+                flatten(&key(&i.to_string()), v, row);
+            }
+        }
+        Value::Array(items) => {
+            let joined = items.iter().map(scalar_to_string).collect::<Vec<_>>();
+            row.insert(prefix.to_string(), Value::String(joined.join(";")));
+        }
+        scalar => {
+            row.insert(prefix.to_string(), scalar.clone());
+        }
+    }
+}
+
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn to_csv<T: Serialize>(results: &[T]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut rows = Vec::with_capacity(results.len());
+    let mut columns: Vec<String> = Vec::new();
+
+    for result in results {
+        let mut row = Map::new();
+        flatten("", &serde_json::to_value(result)?, &mut row);
+        for column in row.keys() {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+        rows.push(row);
+    }
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&columns)?;
+    for row in &rows {
+        writer.write_record(
+            columns
+                .iter()
+                .map(|c| row.get(c).map(scalar_to_string).unwrap_or_default()),
+        )?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_accept() {
+        assert_eq!(OutputFormat::from_accept("text/csv"), OutputFormat::Csv);
+        assert_eq!(
+            OutputFormat::from_accept("application/json;q=0.5, text/csv"),
+            OutputFormat::Csv
+        );
+        assert_eq!(
+            OutputFormat::from_accept("text/html,*/*;q=0.8"),
+            OutputFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_to_csv_flattens_nested_fields() {
+        let results = vec![
+            json!({
+                "lineId": "victoria",
+                "timing": { "read": "2024-01-01T12:00:00Z" },
+                "lines": ["a", "b"],
+            }),
+            json!({
+                "lineId": "central",
+                "lineStatuses": [{ "reason": "Signal failure" }],
+            }),
+        ];
+
+        let csv = to_csv(&results).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "lineId,timing.read,lines,lineStatuses.0.reason"
+        );
+        assert_eq!(lines.next().unwrap(), "victoria,2024-01-01T12:00:00Z,a;b,");
+        assert_eq!(lines.next().unwrap(), "central,,,Signal failure");
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_fan_out_streams_context_first() {
+        let options = ResponseOptions {
+            format: OutputFormat::Ndjson,
+            ..Default::default()
+        };
+        let fetches = vec![
+            Box::pin(async { Ok(vec![json!({ "id": 1 }), json!({ "id": 2 })]) })
+                as std::pin::Pin<Box<dyn Future<Output = AppResult<Vec<Value>>> + Send>>,
+            Box::pin(async { Err(AppError::NotFound("line".to_string())) }),
+        ];
+
+        let response = options
+            .respond_fan_out(Instant::now(), "victoria", fetches)
+            .await
+            .unwrap()
+            .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<Value> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["query"], "victoria");
+        assert!(lines[1..].contains(&json!({ "id": 2 })));
+        assert!(lines[1..].iter().any(|line| line["success"] == false));
+    }
+
+    #[test]
+    fn test_to_geojson() {
+        let dataset = crate::dataset::Dataset::load();
+        let collection = to_geojson(&dataset.stations[..1], &Projection::default()).unwrap();
+
+        assert_eq!(collection["type"], "FeatureCollection");
+        let feature = &collection["features"][0];
+        assert_eq!(
+            feature["geometry"],
+            json!({ "type": "Point", "coordinates": [-0.1059, 51.5586] })
+        );
+        assert_eq!(feature["properties"]["stationName"], "Arsenal");
+        assert!(feature["properties"].get("lat").is_none());
+    }
+
+    #[test]
+    fn test_disruption_geojson() {
+        let disruptions: Vec<Disruption> = serde_json::from_value(json!([
+            {
+                "category": "PlannedWork",
+                "affectedRoutes": [{
+                    "name": "Brixton - Stockwell",
+                    "lineString": "[[[-0.1145,51.4627],[-0.1228,51.4723]]]",
+                }],
+                "affectedStops": [{ "naptanId": "940GZZLUBXN", "lat": 51.4627, "lon": -0.1145 }],
+            },
+            {
+                "category": "RealTime",
+                "affectedRoutes": [{ "name": "Whole line", "lineString": "[]" }],
+            },
+        ]))
+        .unwrap();
+        let collection = to_geojson(&disruptions, &Projection::default()).unwrap();
+        let features = collection["features"].as_array().unwrap();
+
+        assert_eq!(features.len(), 3);
+        assert_eq!(features[0]["geometry"]["type"], "LineString");
+        assert_eq!(features[0]["properties"]["category"], "PlannedWork");
+        let route = &features[0]["properties"]["affectedRoute"];
+        assert_eq!(route["name"], "Brixton - Stockwell");
+        assert!(route.get("lineString").is_none());
+        assert!(features[0]["properties"].get("affectedStops").is_none());
+        assert_eq!(
+            features[1]["geometry"],
+            json!({ "type": "Point", "coordinates": [-0.1145, 51.4627] })
+        );
+        let stop = &features[1]["properties"]["affectedStop"];
+        assert_eq!(stop["naptanId"], "940GZZLUBXN");
+        assert!(stop.get("lat").is_none());
+
+        // Without any geometry the disruption is still listed, minus its line strings
+        assert!(features[2]["geometry"].is_null());
+        let routes = &features[2]["properties"]["affectedRoutes"];
+        assert_eq!(routes[0]["name"], "Whole line");
+        assert!(routes[0].get("lineString").is_none());
+    }
+
+    #[test]
+    fn test_conditional_get() {
+        let dataset = crate::dataset::Dataset::load();
+        let response = || create_response(Instant::now(), "", dataset.stations.clone());
+
+        let options = ResponseOptions::default();
+        let first = options.respond(response()).into_response();
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers()[CACHE_CONTROL], "public, max-age=3600");
+        let etag = first.headers()[ETAG].to_str().unwrap().to_string();
+
+        // The context differs between responses, but the ETag doesn't
+        let options = ResponseOptions {
+            if_none_match: Some(format!("\"other\", {}", etag)),
+            ..Default::default()
+        };
+        let second = options.respond(response()).into_response();
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(second.headers()[ETAG], etag.as_str());
+        assert!(etag.starts_with("W/\""));
+
+        // Weak comparison ignores the W/ prefix
+        let options = ResponseOptions {
+            if_none_match: Some(etag.trim_start_matches("W/").to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.respond(response()).into_response().status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        // A different representation has a different tag
+        let options = ResponseOptions {
+            projection: Projection::new(Some("stationName"), None),
+            if_none_match: Some(etag),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.respond(response()).into_response().status(),
+            StatusCode::OK
+        );
+    }
+}
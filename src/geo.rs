@@ -0,0 +1,328 @@
+use rstar::{primitives::GeomWithData, RTree};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::models::{RouteSection, Station, StationPoint};
+
+// Mean Earth radius in metres, as used by the haversine formula
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+// Great-circle distance in metres between two WGS84 points
+pub fn haversine_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lon2 - lon1).to_radians();
+
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+// Position on the unit sphere. Straight-line (chord) distance between these points
+// increases monotonically with great-circle distance, so the R-tree's Euclidean
+// nearest-neighbour ordering is also the haversine ordering.
+fn unit_vector(lat: f64, lon: f64) -> [f64; 3] {
+    let (phi, lambda) = (lat.to_radians(), lon.to_radians());
+    [
+        phi.cos() * lambda.cos(),
+        phi.cos() * lambda.sin(),
+        phi.sin(),
+    ]
+}
+
+// A location in the index: a station's own position or one of its station points
+// (entrances, platforms), with the index of the station in the dataset
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Location {
+    station: usize,
+    lat: f64,
+    lon: f64,
+}
+
+type IndexedPoint = GeomWithData<[f64; 3], Location>;
+
+// R-tree over station and station point locations
+pub struct StationIndex {
+    tree: RTree<IndexedPoint>,
+}
+
+impl StationIndex {
+    pub fn new(stations: &[Station], station_points: &[StationPoint]) -> Self {
+        let centres = stations.iter().enumerate().filter_map(|(station, s)| {
+            Some(Location {
+                station,
+                lat: s.lat?,
+                lon: s.lon?,
+            })
+        });
+        let by_id: HashMap<&str, usize> = stations
+            .iter()
+            .enumerate()
+            .map(|(station, s)| (s.station_unique_id.as_str(), station))
+            .collect();
+        let points = station_points.iter().filter_map(|point| {
+            Some(Location {
+                station: *by_id.get(point.station_unique_id.as_str())?,
+                lat: point.lat,
+                lon: point.lon,
+            })
+        });
+
+        let points = centres
+            .chain(points)
+            .map(|location| IndexedPoint::new(unit_vector(location.lat, location.lon), location))
+            .collect();
+        Self {
+            tree: RTree::bulk_load(points),
+        }
+    }
+
+    // Station indices nearest to (lat, lon) with their haversine distance in metres
+    // to the station or whichever of its points is closest, closest first. Stops at
+    // `k` matches accepted by `filter`, or once `max_m` is passed.
+    pub fn nearest<F>(
+        &self,
+        stations: &[Station],
+        lat: f64,
+        lon: f64,
+        k: usize,
+        max_m: Option<f64>,
+        filter: F,
+    ) -> Vec<(usize, f64)>
+    where
+        F: Fn(&Station) -> bool,
+    {
+        let mut found = Vec::with_capacity(k);
+        for point in self.tree.nearest_neighbor_iter(&unit_vector(lat, lon)) {
+            if found.len() >= k {
+                break;
+            }
+            let location = point.data;
+            let distance = haversine_m(lat, lon, location.lat, location.lon);
+            if max_m.is_some_and(|max_m| distance > max_m) {
+                break;
+            }
+            // A station is met first at its closest location
+            let seen = found
+                .iter()
+                .any(|(station, _)| *station == location.station);
+            if !seen && filter(&stations[location.station]) {
+                found.push((location.station, distance));
+            }
+        }
+        found
+    }
+}
+
+// GeoJSON geometry, with coordinates in [lon, lat] order
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    Point { coordinates: [f64; 2] },
+    LineString { coordinates: Vec<[f64; 2]> },
+    MultiLineString { coordinates: Vec<Vec<[f64; 2]>> },
+}
+
+impl Geometry {
+    pub fn point(lat: Option<f64>, lon: Option<f64>) -> Option<Self> {
+        Some(Geometry::Point {
+            coordinates: [lon?, lat?],
+        })
+    }
+}
+
+// TfL encodes line strings as JSON text: usually a list of line strings,
+// occasionally a single one
+pub fn parse_line_strings(text: &str) -> Vec<Vec<[f64; 2]>> {
+    serde_json::from_str::<Vec<Vec<[f64; 2]>>>(text)
+        .or_else(|_| serde_json::from_str::<Vec<[f64; 2]>>(text).map(|line| vec![line]))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+impl RouteSection {
+    pub fn geometry(&self) -> Option<Geometry> {
+        let mut lines = parse_line_strings(self.line_string.as_deref()?);
+        match lines.len() {
+            0 => None,
+            1 => Some(Geometry::LineString {
+                coordinates: lines.remove(0),
+            }),
+            _ => Some(Geometry::MultiLineString { coordinates: lines }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_haversine() {
+        // Oval to Stockwell is a little over 1.3km
+        let d = haversine_m(51.4819, -0.1127, 51.4723, -0.1228);
+        assert!((1200.0..1400.0).contains(&d), "{}", d);
+        assert_eq!(haversine_m(51.5, -0.1, 51.5, -0.1), 0.0);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let dataset = Dataset::load();
+        let stations = &dataset.stations;
+
+        // Just north of Oval
+        let found = dataset
+            .spatial
+            .nearest(stations, 51.4830, -0.1130, 2, None, |_| true);
+        let ids: Vec<&str> = found
+            .iter()
+            .map(|(i, _)| stations[*i].station_unique_id.as_str())
+            .collect();
+        assert_eq!(ids, ["940GZZLUOVL", "940GZZLUSKW"]);
+
+        let found = dataset
+            .spatial
+            .nearest(stations, 51.4830, -0.1130, 5, Some(500.0), |_| true);
+        assert_eq!(found.len(), 1);
+
+        let found = dataset
+            .spatial
+            .nearest(stations, 51.4830, -0.1130, 1, None, |s| {
+                s.lines
+                    .as_ref()
+                    .is_some_and(|l| l.contains(&"victoria".to_string()))
+            });
+        assert_eq!(stations[found[0].0].station_unique_id, "940GZZLUSKW");
+    }
+
+    #[test]
+    fn test_nearest_by_station_point() {
+        let stations: Vec<Station> = serde_json::from_value(serde_json::json!([
+            { "stationUniqueId": "A", "stationName": "A", "lat": 51.5000, "lon": -0.1000 },
+            { "stationUniqueId": "B", "stationName": "B", "lat": 51.5030, "lon": -0.1000 },
+        ]))
+        .unwrap();
+        let points: Vec<StationPoint> = serde_json::from_value(serde_json::json!([
+            { "uniqueId": "B-1", "stationUniqueId": "B", "areaName": "", "areaId": 1,
+              "level": 0, "lat": 51.5011, "lon": -0.1000, "friendlyName": "B Entrance" },
+        ]))
+        .unwrap();
+
+        // B's entrance is nearer than A's centre, and B is only listed once
+        let index = StationIndex::new(&stations, &points);
+        let found = index.nearest(&stations, 51.5007, -0.1000, 5, None, |_| true);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].0, 1);
+        assert!((40.0..50.0).contains(&found[0].1), "{}", found[0].1);
+    }
+
+    // Many more stations and points than London has, spread across Greater London
+    fn large_grid() -> (Vec<Station>, Vec<StationPoint>) {
+        let stations: Vec<Station> = (0..10_000)
+            .map(|i| {
+                serde_json::from_value(serde_json::json!({
+                    "stationUniqueId": format!("S{}", i),
+                    "stationName": format!("Station {}", i),
+                    "lat": 51.28 + (i / 100) as f64 * 0.004,
+                    "lon": -0.51 + (i % 100) as f64 * 0.008,
+                }))
+                .unwrap()
+            })
+            .collect();
+        let points = stations
+            .iter()
+            .flat_map(|station| {
+                (0..3).map(move |n| StationPoint {
+                    unique_id: format!("{}-{}", station.station_unique_id, n),
+                    station_unique_id: station.station_unique_id.clone(),
+                    area_name: String::new(),
+                    area_id: n,
+                    level: 0,
+                    lat: station.lat.unwrap() + 0.0002 * n as f64,
+                    lon: station.lon.unwrap(),
+                    friendly_name: String::new(),
+                })
+            })
+            .collect();
+        (stations, points)
+    }
+
+    fn lookup_point(i: u32) -> (f64, f64) {
+        (
+            51.3 + (i % 50) as f64 * 0.007,
+            -0.5 + (i % 37) as f64 * 0.02,
+        )
+    }
+
+    #[test]
+    fn test_nearest_matches_brute_force() {
+        let (stations, points) = large_grid();
+        let index = StationIndex::new(&stations, &points);
+
+        for i in 0..50 {
+            let (lat, lon) = lookup_point(i);
+            let found = index.nearest(&stations, lat, lon, 5, Some(2_000.0), |_| true);
+
+            let mut expected: Vec<f64> = stations
+                .iter()
+                .map(|station| {
+                    points
+                        .iter()
+                        .filter(|p| p.station_unique_id == station.station_unique_id)
+                        .map(|p| (p.lat, p.lon))
+                        .chain([(station.lat.unwrap(), station.lon.unwrap())])
+                        .map(|(p_lat, p_lon)| haversine_m(lat, lon, p_lat, p_lon))
+                        .fold(f64::INFINITY, f64::min)
+                })
+                .filter(|distance| *distance <= 2_000.0)
+                .collect();
+            expected.sort_by(f64::total_cmp);
+            expected.truncate(5);
+
+            let distances: Vec<f64> = found.iter().map(|(_, distance)| *distance).collect();
+            assert_eq!(distances, expected, "lookup at {}, {}", lat, lon);
+        }
+    }
+
+    // Wall-clock timing only means anything in an optimised build:
+    // cargo test --release -- --ignored test_nearest_is_fast
+    #[test]
+    #[ignore]
+    fn test_nearest_is_fast() {
+        let (stations, points) = large_grid();
+        let index = StationIndex::new(&stations, &points);
+
+        let lookups = 1_000;
+        let started = Instant::now();
+        for i in 0..lookups {
+            let (lat, lon) = lookup_point(i);
+            let found = index.nearest(&stations, lat, lon, 5, Some(2_000.0), |_| true);
+            assert_eq!(found.len(), 5);
+        }
+        let per_lookup = started.elapsed() / lookups;
+        assert!(
+            per_lookup < Duration::from_millis(1),
+            "{:?} per lookup",
+            per_lookup
+        );
+    }
+
+    #[test]
+    fn test_route_section_geometry() {
+        let section: RouteSection = serde_json::from_value(serde_json::json!({
+            "lineString": "[[[-0.1127,51.4819],[-0.1228,51.4723]]]",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(section.geometry().unwrap()).unwrap(),
+            serde_json::json!({
+                "type": "LineString",
+                "coordinates": [[-0.1127, 51.4819], [-0.1228, 51.4723]],
+            })
+        );
+    }
+}
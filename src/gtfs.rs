@@ -0,0 +1,733 @@
+use axum::body::Bytes;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures::future::try_join_all;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Cursor, Write};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::dataset::{line_mode, Dataset, TUBE_LINES};
+use crate::error::{AppError, AppResult};
+use crate::geo::{haversine_m, parse_line_strings};
+use crate::models::{MatchedStop, RouteSequence, TimetableResponse};
+use crate::tfl::TflClient;
+
+// Builds a GTFS static feed from the station dataset plus each line's route
+// sequences (stops and shapes) and timetables (trips and stop times).
+//
+// Stop hierarchy: hubs are stations (location_type 1), the NaPTAN stations that
+// trips call at are stops (0) within their hub, and platforms are boarding areas (4)
+// within their station.
+
+const AGENCY_ID: &str = "TfL";
+// Lines fetched at once, and timetables at once within each line, so a feed of
+// every tube line makes at most 16 TfL requests at a time
+const LINE_CONCURRENCY: usize = 4;
+const TIMETABLE_CONCURRENCY: usize = 4;
+// How long a built feed is served before it's rebuilt
+const FEED_MAX_AGE: Duration = Duration::hours(1);
+const DIRECTIONS: [&str; 2] = ["outbound", "inbound"];
+const DAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+// Everything fetched from TfL for one line
+pub struct LineData {
+    pub line_id: String,
+    pub route_sequences: Vec<RouteSequence>,
+    pub timetables: Vec<TimetableResponse>,
+}
+
+pub async fn fetch_line_data(tfl_client: &TflClient, line_id: &str) -> AppResult<LineData> {
+    let route_sequences = try_join_all(
+        DIRECTIONS
+            .iter()
+            .map(|direction| tfl_client.get_route_sequence(line_id, direction)),
+    )
+    .await?;
+
+    // Timetables are published per departure stop, so fetch one from the start of
+    // each distinct route. The origins are owned, as a stream over borrowed ones
+    // makes the handler's future not Send.
+    let origins: Vec<String> = route_sequences
+        .iter()
+        .flat_map(|sequence| &sequence.ordered_line_routes)
+        .filter_map(|route| route.naptan_ids.first())
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let fetches = origins.clone().into_iter().enumerate();
+    let mut results: Vec<_> =
+        stream::iter(fetches)
+            .map(|(index, origin)| async move {
+                (index, tfl_client.get_timetable(line_id, &origin).await)
+            })
+            .buffer_unordered(TIMETABLE_CONCURRENCY)
+            .collect()
+            .await;
+    results.sort_by_key(|(index, _)| *index);
+
+    let mut timetables = Vec::new();
+    for (origin, (_, result)) in origins.iter().zip(results) {
+        match result {
+            Ok(timetable) => timetables.push(timetable),
+            Err(e) => warn!("No timetable for {} from {}: {}", line_id, origin, e),
+        }
+    }
+
+    Ok(LineData {
+        line_id: line_id.to_string(),
+        route_sequences,
+        timetables,
+    })
+}
+
+// Fetch the given lines (all tube lines if none) and build a zipped feed
+pub async fn export(
+    tfl_client: &TflClient,
+    dataset: &Dataset,
+    line_ids: &[String],
+) -> AppResult<Vec<u8>> {
+    let line_ids: Vec<String> = if line_ids.is_empty() {
+        TUBE_LINES.iter().map(|line| line.to_string()).collect()
+    } else {
+        line_ids.to_vec()
+    };
+
+    let mut lines: Vec<LineData> = stream::iter(line_ids.clone())
+        .map(|line_id| async move { fetch_line_data(tfl_client, &line_id).await })
+        .buffer_unordered(LINE_CONCURRENCY)
+        .try_collect()
+        .await?;
+    lines.sort_by_key(|line| line_ids.iter().position(|id| *id == line.line_id));
+
+    build_feed(dataset, &lines, Utc::now().date_naive())
+}
+
+// Built feeds by line list. A feed takes dozens of TfL requests to build, so it's
+// kept for FEED_MAX_AGE (and no later than the day it was built for). Builds hold
+// the lock, so concurrent requests wait for one build rather than starting their own.
+#[derive(Default)]
+pub struct FeedCache {
+    feeds: Mutex<HashMap<Vec<String>, BuiltFeed>>,
+}
+
+struct BuiltFeed {
+    built_at: DateTime<Utc>,
+    zip: Bytes,
+}
+
+impl FeedCache {
+    pub async fn get(
+        &self,
+        tfl_client: &TflClient,
+        dataset: &Dataset,
+        line_ids: &[String],
+    ) -> AppResult<Bytes> {
+        let mut key = line_ids.to_vec();
+        key.sort();
+        key.dedup();
+
+        let mut feeds = self.feeds.lock().await;
+        let now = Utc::now();
+        if let Some(feed) = feeds.get(&key) {
+            if now - feed.built_at < FEED_MAX_AGE && feed.built_at.date_naive() == now.date_naive()
+            {
+                return Ok(feed.zip.clone());
+            }
+        }
+
+        let zip = Bytes::from(export(tfl_client, dataset, &key).await?);
+        feeds.insert(
+            key,
+            BuiltFeed {
+                built_at: now,
+                zip: zip.clone(),
+            },
+        );
+        Ok(zip)
+    }
+}
+
+// CLI entry point: `tb8-rs gtfs [--lines victoria,northern] [--output feed.zip]`
+pub async fn run_cli(args: &[String], dataset: &Dataset) -> AppResult<()> {
+    let mut line_ids = Vec::new();
+    let mut output = "gtfs.zip".to_string();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--lines", Some(lines)) => {
+                line_ids = lines.split(',').map(|l| l.trim().to_string()).collect()
+            }
+            ("--output", Some(path)) => output = path.clone(),
+            _ => {
+                return Err(AppError::ParseError(format!(
+                "Unexpected argument '{}'. Usage: tb8-rs gtfs [--lines a,b] [--output feed.zip]",
+                arg
+            )))
+            }
+        }
+    }
+
+    let feed = export(&TflClient::new(), dataset, &line_ids).await?;
+    std::fs::write(&output, &feed)
+        .map_err(|e| AppError::InternalError(format!("Failed to write {}: {}", output, e)))?;
+
+    info!("Wrote GTFS feed to {} ({} bytes)", output, feed.len());
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Agency {
+    agency_id: &'static str,
+    agency_name: &'static str,
+    agency_url: &'static str,
+    agency_timezone: &'static str,
+    agency_lang: &'static str,
+}
+
+#[derive(Serialize)]
+struct Stop {
+    stop_id: String,
+    stop_name: String,
+    stop_lat: Option<f64>,
+    stop_lon: Option<f64>,
+    location_type: u8,
+    parent_station: Option<String>,
+    platform_code: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Route {
+    route_id: String,
+    agency_id: &'static str,
+    route_short_name: String,
+    route_long_name: String,
+    route_type: u16,
+}
+
+#[derive(Serialize)]
+struct Trip {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    direction_id: u8,
+    shape_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StopTime {
+    trip_id: String,
+    arrival_time: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: usize,
+}
+
+#[derive(Serialize)]
+struct Calendar {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(Serialize)]
+struct ShapePoint {
+    shape_id: String,
+    shape_pt_lat: f64,
+    shape_pt_lon: f64,
+    shape_pt_sequence: usize,
+}
+
+struct Shape {
+    id: String,
+    points: Vec<[f64; 2]>,
+}
+
+pub fn build_feed(dataset: &Dataset, lines: &[LineData], today: NaiveDate) -> AppResult<Vec<u8>> {
+    let stops = build_stops(dataset, lines);
+
+    let mut routes = Vec::new();
+    let mut trips = Vec::new();
+    let mut stop_times = Vec::new();
+    let mut shape_points = Vec::new();
+    let mut services = BTreeSet::new();
+
+    for line in lines {
+        let first = line.route_sequences.first();
+        let line_name = first
+            .and_then(|s| s.line_name.clone())
+            .unwrap_or_else(|| line.line_id.clone());
+        let mode = first
+            .and_then(|s| s.mode.clone())
+            .or_else(|| line_mode(&line.line_id).map(str::to_string))
+            .unwrap_or_default();
+        routes.push(Route {
+            route_id: line.line_id.clone(),
+            agency_id: AGENCY_ID,
+            route_short_name: line_name.clone(),
+            route_long_name: format!("{} line", line_name),
+            route_type: route_type(&mode),
+        });
+
+        let shapes = line_shapes(line);
+        for shape in &shapes {
+            shape_points.extend(shape.points.iter().enumerate().map(|(i, [lon, lat])| {
+                ShapePoint {
+                    shape_id: shape.id.clone(),
+                    shape_pt_lat: *lat,
+                    shape_pt_lon: *lon,
+                    shape_pt_sequence: i,
+                }
+            }));
+        }
+
+        for timetable_response in &line.timetables {
+            let Some(timetable) = &timetable_response.timetable else {
+                continue;
+            };
+            let Some(departure_stop) = &timetable.departure_stop_id else {
+                continue;
+            };
+            let direction_id = match timetable_response.direction.as_deref() {
+                Some("inbound") => 1,
+                _ => 0,
+            };
+
+            for (ri, route) in timetable.routes.iter().enumerate() {
+                for (si, schedule) in route.schedules.iter().enumerate() {
+                    let service_id = slug(schedule.name.as_deref().unwrap_or("daily"));
+
+                    for (ji, journey) in schedule.known_journeys.iter().enumerate() {
+                        let start = journey.hour.as_deref().and_then(|h| h.parse::<f64>().ok());
+                        let minute = journey
+                            .minute
+                            .as_deref()
+                            .and_then(|m| m.parse::<f64>().ok());
+                        let interval_id = journey.interval_id.map(|id| id.to_string());
+                        let intervals = route
+                            .station_intervals
+                            .iter()
+                            .find(|si| si.id == interval_id)
+                            .map(|si| &si.intervals);
+                        let (Some(hour), Some(minute), Some(intervals)) =
+                            (start, minute, intervals)
+                        else {
+                            continue;
+                        };
+                        let start = hour * 60.0 + minute;
+
+                        let trip_id =
+                            format!("{}-{}-{}-{}-{}", line.line_id, departure_stop, ri, si, ji);
+                        let calls: Vec<(&String, f64)> = std::iter::once((departure_stop, 0.0))
+                            .chain(intervals.iter().filter_map(|interval| {
+                                Some((interval.stop_id.as_ref()?, interval.time_to_arrival?))
+                            }))
+                            .collect();
+
+                        let shape_id = nearest_shape(&shapes, &stops, &calls);
+                        for (sequence, (stop_id, offset)) in calls.into_iter().enumerate() {
+                            let time = gtfs_time(start + offset);
+                            stop_times.push(StopTime {
+                                trip_id: trip_id.clone(),
+                                arrival_time: time.clone(),
+                                departure_time: time,
+                                stop_id: stop_id.clone(),
+                                stop_sequence: sequence,
+                            });
+                        }
+
+                        services.insert((service_id.clone(), schedule.name.clone()));
+                        trips.push(Trip {
+                            route_id: line.line_id.clone(),
+                            service_id: service_id.clone(),
+                            trip_id,
+                            direction_id,
+                            shape_id,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    let start_date = today.format("%Y%m%d").to_string();
+    let end_date = (today + Duration::days(365)).format("%Y%m%d").to_string();
+    let calendar: Vec<Calendar> = services
+        .into_iter()
+        .map(|(service_id, name)| {
+            let days = service_days(name.as_deref().unwrap_or_default());
+            Calendar {
+                service_id,
+                monday: days[0] as u8,
+                tuesday: days[1] as u8,
+                wednesday: days[2] as u8,
+                thursday: days[3] as u8,
+                friday: days[4] as u8,
+                saturday: days[5] as u8,
+                sunday: days[6] as u8,
+                start_date: start_date.clone(),
+                end_date: end_date.clone(),
+            }
+        })
+        .collect();
+
+    let agency = [Agency {
+        agency_id: AGENCY_ID,
+        agency_name: "Transport for London",
+        agency_url: "https://tfl.gov.uk",
+        agency_timezone: "Europe/London",
+        agency_lang: "en",
+    }];
+    let stops: Vec<Stop> = stops.into_values().collect();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    write_file(&mut zip, "agency.txt", &agency)?;
+    write_file(&mut zip, "stops.txt", &stops)?;
+    write_file(&mut zip, "routes.txt", &routes)?;
+    write_file(&mut zip, "trips.txt", &trips)?;
+    write_file(&mut zip, "stop_times.txt", &stop_times)?;
+    write_file(&mut zip, "calendar.txt", &calendar)?;
+    write_file(&mut zip, "shapes.txt", &shape_points)?;
+
+    let cursor = zip
+        .finish()
+        .map_err(|e| AppError::InternalError(format!("Failed to write GTFS zip: {}", e)))?;
+    Ok(cursor.into_inner())
+}
+
+fn build_stops(dataset: &Dataset, lines: &[LineData]) -> BTreeMap<String, Stop> {
+    let mut stops = BTreeMap::new();
+
+    for station in &dataset.stations {
+        if let Some(hub) = &station.hub_naptan_code {
+            stops.entry(hub.clone()).or_insert_with(|| Stop {
+                stop_id: hub.clone(),
+                stop_name: station.station_name.clone(),
+                stop_lat: station.lat,
+                stop_lon: station.lon,
+                location_type: 1,
+                parent_station: None,
+                platform_code: None,
+            });
+        }
+        stops.insert(
+            station.station_unique_id.clone(),
+            Stop {
+                stop_id: station.station_unique_id.clone(),
+                stop_name: station.station_name.clone(),
+                stop_lat: station.lat,
+                stop_lon: station.lon,
+                location_type: 0,
+                parent_station: station.hub_naptan_code.clone(),
+                platform_code: None,
+            },
+        );
+    }
+
+    for platform in &dataset.platforms {
+        let field = |name: &str| platform[name].as_str().map(str::to_string);
+        let (Some(stop_id), Some(station_id)) =
+            (field("PlatformNaptanCode"), field("StationUniqueId"))
+        else {
+            continue;
+        };
+        if !stops.contains_key(&station_id) {
+            continue;
+        }
+        stops.insert(
+            stop_id.clone(),
+            Stop {
+                stop_name: field("PlatformFriendlyName").unwrap_or_else(|| stop_id.clone()),
+                stop_id,
+                stop_lat: None,
+                stop_lon: None,
+                location_type: 4,
+                parent_station: Some(station_id),
+                platform_code: field("PlatformNumber"),
+            },
+        );
+    }
+
+    // Stops that are served but missing from the dataset
+    let matched_stops = lines.iter().flat_map(|line| {
+        line.route_sequences
+            .iter()
+            .flat_map(|s| {
+                s.stations.iter().chain(
+                    s.stop_point_sequences
+                        .iter()
+                        .flat_map(|sps| &sps.stop_point),
+                )
+            })
+            .chain(
+                line.timetables
+                    .iter()
+                    .flat_map(|t| t.stations.iter().chain(&t.stops)),
+            )
+    });
+    for matched in matched_stops.collect::<Vec<&MatchedStop>>() {
+        let Some(id) = matched.station_id.as_ref().or(matched.id.as_ref()) else {
+            continue;
+        };
+        if stops.contains_key(id) {
+            continue;
+        }
+        let parent_station = matched
+            .top_most_parent_id
+            .clone()
+            .filter(|parent| stops.get(parent).is_some_and(|p| p.location_type == 1));
+        stops.insert(
+            id.clone(),
+            Stop {
+                stop_id: id.clone(),
+                stop_name: matched.name.clone().unwrap_or_else(|| id.clone()),
+                stop_lat: matched.lat,
+                stop_lon: matched.lon,
+                location_type: 0,
+                parent_station,
+                platform_code: None,
+            },
+        );
+    }
+
+    stops
+}
+
+// One shape per polyline in each direction's route sequence
+fn line_shapes(line: &LineData) -> Vec<Shape> {
+    line.route_sequences
+        .iter()
+        .flat_map(|sequence| {
+            let direction = sequence.direction.clone().unwrap_or_default();
+            sequence
+                .line_strings
+                .iter()
+                .flat_map(|text| parse_line_strings(text))
+                .enumerate()
+                .map(move |(i, points)| Shape {
+                    id: format!("{}-{}-{}", line.line_id, direction, i),
+                    points,
+                })
+        })
+        .collect()
+}
+
+// The shape whose ends lie closest to a trip's first and last stops
+fn nearest_shape(
+    shapes: &[Shape],
+    stops: &BTreeMap<String, Stop>,
+    calls: &[(&String, f64)],
+) -> Option<String> {
+    let position = |stop_id: &String| {
+        let stop = stops.get(stop_id)?;
+        Some((stop.stop_lat?, stop.stop_lon?))
+    };
+    let (first_lat, first_lon) = position(calls.first()?.0)?;
+    let (last_lat, last_lon) = position(calls.last()?.0)?;
+
+    shapes
+        .iter()
+        .filter_map(|shape| {
+            let [start_lon, start_lat] = shape.points.first()?;
+            let [end_lon, end_lat] = shape.points.last()?;
+            let distance = haversine_m(first_lat, first_lon, *start_lat, *start_lon)
+                + haversine_m(last_lat, last_lon, *end_lat, *end_lon);
+            Some((distance, &shape.id))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, id)| id.clone())
+}
+
+fn write_file<R: Serialize>(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    rows: &[R],
+) -> AppResult<()> {
+    let to_error = |e: String| AppError::InternalError(format!("Failed to write {}: {}", name, e));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).map_err(|e| to_error(e.to_string()))?;
+    }
+    let bytes = writer.into_inner().map_err(|e| to_error(e.to_string()))?;
+
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(name, options)
+        .map_err(|e| to_error(e.to_string()))?;
+    zip.write_all(&bytes).map_err(|e| to_error(e.to_string()))
+}
+
+// GTFS route_type for a TfL mode
+fn route_type(mode: &str) -> u16 {
+    match mode {
+        "tube" => 1,
+        "dlr" | "tram" => 0,
+        "overground" | "elizabeth-line" | "national-rail" => 2,
+        "bus" => 3,
+        "river-bus" => 4,
+        "cable-car" => 6,
+        _ => 1,
+    }
+}
+
+// Minutes after midnight as HH:MM:SS. GTFS allows hours past 24 for trips that
+// run after midnight.
+fn gtfs_time(minutes: f64) -> String {
+    let seconds = (minutes * 60.0).round() as i64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Days a TfL schedule (e.g. "Monday - Friday", "Saturday", "Sunday - Thursday") runs
+// on, Monday first. Schedules that don't name any day run every day.
+fn service_days(name: &str) -> [bool; 7] {
+    let name = name.to_lowercase();
+    // In the order they're named, as ranges can wrap around the week
+    let mut mentioned: Vec<(usize, usize)> = (0..7)
+        .filter_map(|day| Some((name.find(DAYS[day])?, day)))
+        .collect();
+    mentioned.sort();
+
+    let mut days = [mentioned.is_empty(); 7];
+    match mentioned.as_slice() {
+        [(_, from), (_, to)] if name.contains(" - ") => {
+            let mut day = *from;
+            days[day] = true;
+            while day != *to {
+                day = (day + 1) % 7;
+                days[day] = true;
+            }
+        }
+        _ => mentioned.iter().for_each(|&(_, day)| days[day] = true),
+    }
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_service_days() {
+        assert_eq!(
+            service_days("Monday - Friday"),
+            [true, true, true, true, true, false, false]
+        );
+        assert_eq!(
+            service_days("Saturday"),
+            [false, false, false, false, false, true, false]
+        );
+        assert_eq!(
+            service_days("Sunday - Thursday"),
+            [true, true, true, true, false, false, true]
+        );
+        assert_eq!(
+            service_days("Friday - Sunday"),
+            [false, false, false, false, true, true, true]
+        );
+        assert_eq!(service_days("Daily"), [true; 7]);
+        assert_eq!(gtfs_time(24.0 * 60.0 + 5.5), "24:05:30");
+    }
+
+    #[test]
+    fn test_build_feed() {
+        let dataset = Dataset::load();
+        let line: LineData = LineData {
+            line_id: "victoria".to_string(),
+            route_sequences: vec![serde_json::from_value(serde_json::json!({
+                "lineId": "victoria",
+                "lineName": "Victoria",
+                "direction": "outbound",
+                "mode": "tube",
+                "lineStrings": ["[[[-0.1145,51.4627],[-0.1228,51.4723],[-0.1447,51.4965]]]"],
+            }))
+            .unwrap()],
+            timetables: vec![serde_json::from_value(serde_json::json!({
+                "lineId": "victoria",
+                "direction": "outbound",
+                "timetable": {
+                    "departureStopId": "940GZZLUBXN",
+                    "routes": [{
+                        "stationIntervals": [{
+                            "id": "0",
+                            "intervals": [
+                                { "stopId": "940GZZLUSKW", "timeToArrival": 2.0 },
+                                { "stopId": "940GZZLUVIC", "timeToArrival": 7.5 },
+                            ],
+                        }],
+                        "schedules": [{
+                            "name": "Monday - Friday",
+                            "knownJourneys": [{ "hour": "5", "minute": "30", "intervalId": 0 }],
+                        }],
+                    }],
+                },
+            }))
+            .unwrap()],
+        };
+
+        let feed = build_feed(
+            &dataset,
+            &[line],
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        )
+        .unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(feed)).unwrap();
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+
+        let stop_times = read("stop_times.txt");
+        assert!(stop_times.contains("05:30:00,05:30:00,940GZZLUBXN,0"));
+        assert!(stop_times.contains("05:37:30,05:37:30,940GZZLUVIC,2"));
+
+        let trips = read("trips.txt");
+        assert!(trips
+            .contains("victoria,monday-friday,victoria-940GZZLUBXN-0-0-0,0,victoria-outbound-0"));
+
+        let stops = read("stops.txt");
+        assert!(stops.contains("HUBVIC,Victoria,51.4965,-0.1447,1,,"));
+        assert!(stops.contains("940GZZLUVIC,Victoria,51.4965,-0.1447,0,HUBVIC,"));
+        assert!(stops.contains("940GZZLUASL1,Northbound Platform 1,,,4,940GZZLUASL,1"));
+
+        assert!(read("calendar.txt").contains("monday-friday,1,1,1,1,1,0,0,20240101,20241231"));
+    }
+}
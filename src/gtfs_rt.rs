@@ -0,0 +1,450 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::Hash;
+
+use crate::changes::disruption_ids;
+use crate::models::{Disruption, Prediction};
+
+// GTFS-Realtime feeds built from TfL predictions and disruptions.
+//
+// The messages below are the subset of gtfs-realtime.proto we emit, with the same
+// field numbers, so they encode to the standard wire format without a build step.
+// TfL has no scheduled trip ids, so each vehicle's trip is identified by line and
+// vehicle id and marked as unscheduled.
+
+const GTFS_RT_VERSION: &str = "2.0";
+const AGENCY_ID: &str = "TfL";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedMessage {
+    #[prost(message, required, tag = "1")]
+    pub header: FeedHeader,
+    #[prost(message, repeated, tag = "2")]
+    pub entity: Vec<FeedEntity>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedHeader {
+    #[prost(string, required, tag = "1")]
+    pub gtfs_realtime_version: String,
+    // FULL_DATASET
+    #[prost(int32, optional, tag = "2")]
+    pub incrementality: Option<i32>,
+    #[prost(uint64, optional, tag = "3")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(message, optional, tag = "3")]
+    pub trip_update: Option<TripUpdate>,
+    #[prost(message, optional, tag = "5")]
+    pub alert: Option<Alert>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TripUpdate {
+    #[prost(message, required, tag = "1")]
+    pub trip: TripDescriptor,
+    #[prost(message, repeated, tag = "2")]
+    pub stop_time_update: Vec<StopTimeUpdate>,
+    #[prost(message, optional, tag = "3")]
+    pub vehicle: Option<VehicleDescriptor>,
+    #[prost(uint64, optional, tag = "4")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub trip_id: Option<String>,
+    #[prost(enumeration = "ScheduleRelationship", optional, tag = "4")]
+    pub schedule_relationship: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    pub route_id: Option<String>,
+    #[prost(uint32, optional, tag = "6")]
+    pub direction_id: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ScheduleRelationship {
+    Scheduled = 0,
+    Added = 1,
+    Unscheduled = 2,
+    Canceled = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VehicleDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StopTimeUpdate {
+    #[prost(uint32, optional, tag = "1")]
+    pub stop_sequence: Option<u32>,
+    #[prost(message, optional, tag = "2")]
+    pub arrival: Option<StopTimeEvent>,
+    #[prost(string, optional, tag = "4")]
+    pub stop_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StopTimeEvent {
+    #[prost(int64, optional, tag = "2")]
+    pub time: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Alert {
+    #[prost(message, repeated, tag = "1")]
+    pub active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    pub informed_entity: Vec<EntitySelector>,
+    #[prost(enumeration = "Cause", optional, tag = "6")]
+    pub cause: Option<i32>,
+    #[prost(enumeration = "Effect", optional, tag = "7")]
+    pub effect: Option<i32>,
+    #[prost(message, optional, tag = "10")]
+    pub header_text: Option<TranslatedString>,
+    #[prost(message, optional, tag = "11")]
+    pub description_text: Option<TranslatedString>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeRange {
+    #[prost(uint64, optional, tag = "1")]
+    pub start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub end: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EntitySelector {
+    #[prost(string, optional, tag = "1")]
+    pub agency_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub route_id: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub stop_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TranslatedString {
+    #[prost(message, repeated, tag = "1")]
+    pub translation: Vec<Translation>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Translation {
+    #[prost(string, required, tag = "1")]
+    pub text: String,
+    #[prost(string, optional, tag = "2")]
+    pub language: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+#[allow(clippy::enum_variant_names)] // mirrors the proto enum
+pub enum Cause {
+    UnknownCause = 1,
+    OtherCause = 2,
+    TechnicalProblem = 3,
+    Strike = 4,
+    Demonstration = 5,
+    Accident = 6,
+    Holiday = 7,
+    Weather = 8,
+    Maintenance = 9,
+    Construction = 10,
+    PoliceActivity = 11,
+    MedicalEmergency = 12,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+#[allow(clippy::enum_variant_names)] // mirrors the proto enum
+pub enum Effect {
+    NoService = 1,
+    ReducedService = 2,
+    SignificantDelays = 3,
+    Detour = 4,
+    AdditionalService = 5,
+    ModifiedService = 6,
+    OtherEffect = 7,
+    UnknownEffect = 8,
+    StopMoved = 9,
+}
+
+fn header(now: DateTime<Utc>) -> FeedHeader {
+    FeedHeader {
+        gtfs_realtime_version: GTFS_RT_VERSION.to_string(),
+        incrementality: Some(0),
+        timestamp: Some(now.timestamp() as u64),
+    }
+}
+
+fn text(text: &str) -> TranslatedString {
+    TranslatedString {
+        translation: vec![Translation {
+            text: text.to_string(),
+            language: Some("en".to_string()),
+        }],
+    }
+}
+
+// Predictions grouped into each vehicle's journey on a line, keyed by line and
+// vehicle id, with its calls in order of expected arrival
+pub fn group_by_vehicle(predictions: &[Prediction]) -> BTreeMap<(&str, &str), Vec<&Prediction>> {
+    let mut by_vehicle: BTreeMap<(&str, &str), Vec<&Prediction>> = BTreeMap::new();
+    for prediction in predictions {
+        let (Some(line_id), Some(vehicle_id), Some(_)) = (
+            prediction.line_id.as_deref(),
+            prediction.vehicle_id.as_deref(),
+            prediction.expected_arrival,
+        ) else {
+            continue;
+        };
+        by_vehicle
+            .entry((line_id, vehicle_id))
+            .or_default()
+            .push(prediction);
+    }
+    for calls in by_vehicle.values_mut() {
+        calls.sort_by_key(|p| p.expected_arrival);
+    }
+    by_vehicle
+}
+
+// One TripUpdate per vehicle on each line, with its predicted arrivals in order
+pub fn trip_updates(predictions: &[Prediction], now: DateTime<Utc>) -> FeedMessage {
+    let by_vehicle = group_by_vehicle(predictions);
+
+    let entity = by_vehicle
+        .into_iter()
+        .map(|((line_id, vehicle_id), predictions)| {
+            let trip_id = format!("{}-{}", line_id, vehicle_id);
+
+            let stop_time_update = predictions
+                .iter()
+                .map(|p| StopTimeUpdate {
+                    stop_sequence: None,
+                    arrival: Some(StopTimeEvent {
+                        time: p.expected_arrival.map(|t| t.timestamp()),
+                    }),
+                    stop_id: p.naptan_id.clone(),
+                })
+                .collect();
+            let read = predictions
+                .iter()
+                .filter_map(|p| p.timing.as_ref().and_then(|t| t.read).or(p.timestamp))
+                .max();
+
+            FeedEntity {
+                id: trip_id.clone(),
+                trip_update: Some(TripUpdate {
+                    trip: TripDescriptor {
+                        trip_id: Some(trip_id),
+                        schedule_relationship: Some(ScheduleRelationship::Unscheduled as i32),
+                        route_id: Some(line_id.to_string()),
+                        direction_id: match predictions[0].direction.as_deref() {
+                            Some("outbound") => Some(0),
+                            Some("inbound") => Some(1),
+                            _ => None,
+                        },
+                    },
+                    stop_time_update,
+                    vehicle: Some(VehicleDescriptor {
+                        id: Some(vehicle_id.to_string()),
+                        label: predictions[0].destination_name.clone(),
+                    }),
+                    timestamp: read.map(|t| t.timestamp() as u64),
+                }),
+                alert: None,
+            }
+        })
+        .collect();
+
+    FeedMessage {
+        header: header(now),
+        entity,
+    }
+}
+
+// One Alert per disruption, informing the affected lines and stops. Alerts have
+// the disruption's id from the change feed.
+pub fn alerts(disruptions: &[Disruption], now: DateTime<Utc>) -> FeedMessage {
+    let entity = disruption_ids(disruptions)
+        .into_iter()
+        .zip(disruptions)
+        .map(|(id, disruption)| FeedEntity {
+            id,
+            trip_update: None,
+            alert: Some(alert(disruption)),
+        })
+        .collect();
+
+    FeedMessage {
+        header: header(now),
+        entity,
+    }
+}
+
+fn alert(disruption: &Disruption) -> Alert {
+    let routes: BTreeSet<&String> = disruption
+        .affected_routes
+        .iter()
+        .filter_map(|route| route.line_id.as_ref())
+        .collect();
+    let stops: BTreeSet<&String> = disruption
+        .affected_stops
+        .iter()
+        .filter_map(|stop| stop.naptan_id.as_ref().or(stop.id.as_ref()))
+        .collect();
+
+    let mut informed_entity: Vec<EntitySelector> = routes
+        .into_iter()
+        .map(|route_id| EntitySelector {
+            route_id: Some(route_id.clone()),
+            ..Default::default()
+        })
+        .chain(stops.into_iter().map(|stop_id| EntitySelector {
+            stop_id: Some(stop_id.clone()),
+            ..Default::default()
+        }))
+        .collect();
+    // Alerts must inform at least one entity
+    if informed_entity.is_empty() {
+        informed_entity.push(EntitySelector {
+            agency_id: Some(AGENCY_ID.to_string()),
+            ..Default::default()
+        });
+    }
+
+    let start = disruption
+        .affected_routes
+        .iter()
+        .filter_map(|route| route.valid_from)
+        .min()
+        .or(disruption.created);
+    let end = disruption
+        .affected_routes
+        .iter()
+        .filter_map(|route| route.valid_to)
+        .max();
+    let active_period = if start.is_some() || end.is_some() {
+        vec![TimeRange {
+            start: start.map(|t| t.timestamp() as u64),
+            end: end.map(|t| t.timestamp() as u64),
+        }]
+    } else {
+        Vec::new()
+    };
+
+    let header_text = disruption
+        .summary
+        .as_deref()
+        .or(disruption.category_description.as_deref())
+        .or(disruption.description.as_deref());
+
+    Alert {
+        active_period,
+        informed_entity,
+        cause: Some(cause(disruption) as i32),
+        effect: Some(effect(disruption) as i32),
+        header_text: header_text.map(text),
+        description_text: disruption.description.as_deref().map(text),
+    }
+}
+
+fn cause(disruption: &Disruption) -> Cause {
+    match disruption.category.as_deref() {
+        Some("PlannedWork") => Cause::Maintenance,
+        Some("Information") | Some("Event") => Cause::OtherCause,
+        _ => Cause::UnknownCause,
+    }
+}
+
+fn effect(disruption: &Disruption) -> Effect {
+    match disruption.closure_text.as_deref() {
+        Some("closed") | Some("fullClosure") | Some("suspended") => Effect::NoService,
+        Some("partClosure")
+        | Some("partSuspended")
+        | Some("reducedService")
+        | Some("minorDelays") => Effect::ReducedService,
+        Some("severeDelays") => Effect::SignificantDelays,
+        Some("diverted") => Effect::Detour,
+        Some("specialService") => Effect::ModifiedService,
+        _ => Effect::UnknownEffect,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn test_trip_updates_group_by_vehicle() {
+        let predictions: Vec<Prediction> = serde_json::from_value(serde_json::json!([
+            { "lineId": "victoria", "vehicleId": "201", "naptanId": "940GZZLUVIC",
+              "expectedArrival": "2024-01-01T12:05:00Z", "direction": "inbound" },
+            { "lineId": "victoria", "vehicleId": "201", "naptanId": "940GZZLUOVL",
+              "expectedArrival": "2024-01-01T12:01:00Z", "direction": "inbound" },
+            { "lineId": "victoria", "vehicleId": "202", "naptanId": "940GZZLUBXN",
+              "expectedArrival": "2024-01-01T12:03:00Z" },
+            { "lineId": "victoria", "naptanId": "940GZZLUBXN",
+              "expectedArrival": "2024-01-01T12:04:00Z" },
+        ]))
+        .unwrap();
+
+        let feed = trip_updates(&predictions, Utc::now());
+        let decoded = FeedMessage::decode(feed.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded.entity.len(), 2);
+
+        let update = decoded.entity[0].trip_update.as_ref().unwrap();
+        assert_eq!(update.trip.trip_id.as_deref(), Some("victoria-201"));
+        assert_eq!(update.trip.direction_id, Some(1));
+        let stops: Vec<_> = update
+            .stop_time_update
+            .iter()
+            .map(|s| s.stop_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(stops, ["940GZZLUOVL", "940GZZLUVIC"]);
+    }
+
+    #[test]
+    fn test_alerts_inform_routes_and_stops() {
+        let disruptions: Vec<Disruption> = serde_json::from_value(serde_json::json!([{
+            "category": "RealTime",
+            "description": "Minor delays on the Victoria line",
+            "closureText": "minorDelays",
+            "affectedRoutes": [{ "lineId": "victoria" }, { "lineId": "victoria" }],
+            "affectedStops": [{ "naptanId": "940GZZLUVIC" }],
+        }]))
+        .unwrap();
+
+        let feed = alerts(&disruptions, Utc::now());
+        assert_eq!(
+            feed.entity[0].id,
+            crate::changes::disruption_id(&disruptions[0])
+        );
+        let alert = feed.entity[0].alert.as_ref().unwrap();
+        assert_eq!(alert.informed_entity.len(), 2);
+        assert_eq!(
+            alert.informed_entity[0].route_id.as_deref(),
+            Some("victoria")
+        );
+        assert_eq!(
+            alert.informed_entity[1].stop_id.as_deref(),
+            Some("940GZZLUVIC")
+        );
+        assert_eq!(alert.effect, Some(Effect::ReducedService as i32));
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use crate::models::{HeadwayGap, Prediction, StationHeadways};
+use crate::predictions::merge_shared;
+
+// Headways: the gaps between consecutive trains calling at each station in each
+// direction, from the expected arrivals of a line's live predictions. Long gaps
+// and bunching (a short gap, then a long one) show up without any history.
+
+// Gaps flagged unless `threshold=` says otherwise, in seconds
+pub const DEFAULT_GAP_THRESHOLD: i64 = 600;
+
+// The nearest-rank percentile of sorted values
+pub fn percentile(sorted: &[i64], percent: usize) -> i64 {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn direction(prediction: &Prediction) -> String {
+    prediction
+        .direction
+        .clone()
+        .filter(|d| !d.is_empty())
+        .or_else(|| prediction.platform_name.clone())
+        .unwrap_or_default()
+}
+
+// Headways at every station and direction with at least two trains predicted,
+// flagging gaps longer than `threshold` seconds. Predictions flagged stale aren't
+// counted: a ghost train would hide the very gap it leaves.
+pub fn compute(mut predictions: Vec<Prediction>, threshold: i64) -> Vec<StationHeadways> {
+    predictions.retain(|p| p.stale.is_none());
+    // The same train can be predicted twice at a stop; only count it once
+    let predictions = merge_shared(predictions);
+
+    let mut calls: BTreeMap<(String, String), Vec<&Prediction>> = BTreeMap::new();
+    for prediction in &predictions {
+        let (Some(naptan_id), Some(_)) = (&prediction.naptan_id, prediction.expected_arrival)
+        else {
+            continue;
+        };
+        calls
+            .entry((naptan_id.clone(), direction(prediction)))
+            .or_default()
+            .push(prediction);
+    }
+
+    calls
+        .into_iter()
+        .filter(|(_, trains)| trains.len() > 1)
+        .map(|((naptan_id, direction), mut trains)| {
+            trains.sort_by_key(|p| p.expected_arrival);
+
+            let mut gaps = Vec::new();
+            let mut headways: Vec<i64> = trains
+                .windows(2)
+                .map(|pair| {
+                    let arrival = pair[1].expected_arrival.unwrap_or_default();
+                    let headway =
+                        (arrival - pair[0].expected_arrival.unwrap_or_default()).num_seconds();
+                    if headway > threshold {
+                        gaps.push(HeadwayGap {
+                            after_vehicle_id: pair[0].vehicle_id.clone(),
+                            vehicle_id: pair[1].vehicle_id.clone(),
+                            expected_arrival: arrival,
+                            headway,
+                        });
+                    }
+                    headway
+                })
+                .collect();
+            headways.sort_unstable();
+
+            StationHeadways {
+                naptan_id,
+                station_name: trains[0].station_name.clone(),
+                direction,
+                trains: trains.len(),
+                mean_headway: headways.iter().sum::<i64>() as f64 / headways.len() as f64,
+                median_headway: percentile(&headways, 50),
+                p90_headway: percentile(&headways, 90),
+                max_headway: headways[headways.len() - 1],
+                gaps,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::StaleReason;
+    use serde_json::json;
+
+    fn prediction(vehicle_id: &str, naptan_id: &str, direction: &str, arrival: &str) -> Prediction {
+        serde_json::from_value(json!({
+            "vehicleId": vehicle_id,
+            "naptanId": naptan_id,
+            "direction": direction,
+            "expectedArrival": format!("2024-01-01T{}Z", arrival),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compute_headways() {
+        let headways = compute(
+            vec![
+                prediction("1", "940GZZLUVIC", "outbound", "12:00:00"),
+                prediction("3", "940GZZLUVIC", "outbound", "12:13:00"),
+                prediction("2", "940GZZLUVIC", "outbound", "12:02:00"),
+                prediction("4", "940GZZLUVIC", "outbound", "12:16:00"),
+                // A train that hasn't moved for a while, in the middle of the gap
+                {
+                    let mut ghost = prediction("9", "940GZZLUVIC", "outbound", "12:08:00");
+                    ghost.stale = Some(StaleReason::NotProgressing);
+                    ghost
+                },
+                // Other direction, and a lone train elsewhere
+                prediction("5", "940GZZLUVIC", "inbound", "12:01:00"),
+                prediction("6", "940GZZLUVIC", "inbound", "12:04:00"),
+                prediction("7", "940GZZLUGPK", "outbound", "12:05:00"),
+            ],
+            DEFAULT_GAP_THRESHOLD,
+        );
+
+        assert_eq!(headways.len(), 2);
+        let outbound = headways.iter().find(|h| h.direction == "outbound").unwrap();
+        assert_eq!(outbound.trains, 4);
+        assert_eq!(outbound.mean_headway, 320.0);
+        assert_eq!(outbound.median_headway, 180);
+        assert_eq!(outbound.max_headway, 660);
+        assert_eq!(outbound.p90_headway, 660);
+        assert_eq!(outbound.gaps.len(), 1);
+        assert_eq!(outbound.gaps[0].after_vehicle_id.as_deref(), Some("2"));
+        assert_eq!(outbound.gaps[0].headway, 660);
+
+        let inbound = headways.iter().find(|h| h.direction == "inbound").unwrap();
+        assert_eq!(inbound.mean_headway, 180.0);
+        assert!(inbound.gaps.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use futures::Future;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+use crate::error::AppResult;
+
+// Shared upstream pollers for live endpoints. Each topic (e.g. a station and its
+// lines) has at most one poller however many clients subscribe to it; the poller
+// publishes a new snapshot only when the results change, and stops once its last
+// subscriber has gone.
+
+// The latest results for a topic, identified by a hash of their content so clients
+// can tell whether they've already seen them
+#[derive(Debug)]
+pub struct Snapshot<T> {
+    pub id: String,
+    pub results: Vec<T>,
+}
+
+type Latest<T> = Option<Arc<Snapshot<T>>>;
+
+struct Topic<T> {
+    receiver: watch::Receiver<Latest<T>>,
+    task: AbortHandle,
+}
+
+impl<T> Drop for Topic<T> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// A subscriber's handle on a topic. The poller keeps running while any are held.
+pub struct Subscription<T> {
+    _topic: Arc<Topic<T>>,
+    pub receiver: watch::Receiver<Latest<T>>,
+}
+
+pub struct Pollers<T> {
+    interval: Duration,
+    topics: Mutex<HashMap<String, Weak<Topic<T>>>>,
+}
+
+impl<T> Pollers<T>
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Subscribe to a topic, starting its poller with `fetch` if it isn't running
+    pub fn subscribe<F, Fut>(&self, key: &str, fetch: F) -> Subscription<T>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = AppResult<Vec<T>>> + Send + 'static,
+    {
+        let mut topics = self.topics.lock().unwrap();
+        topics.retain(|_, topic| topic.strong_count() > 0);
+
+        if let Some(topic) = topics.get(key).and_then(Weak::upgrade) {
+            return Subscription {
+                receiver: topic.receiver.clone(),
+                _topic: topic,
+            };
+        }
+
+        debug!("Starting poller for {}", key);
+        let (sender, receiver) = watch::channel(None);
+        let task = tokio::spawn(poll(key.to_string(), self.interval, sender, fetch));
+        let topic = Arc::new(Topic {
+            receiver: receiver.clone(),
+            task: task.abort_handle(),
+        });
+        topics.insert(key.to_string(), Arc::downgrade(&topic));
+
+        Subscription {
+            _topic: topic,
+            receiver,
+        }
+    }
+
+    // Number of topics with a running poller
+    pub fn active(&self) -> usize {
+        let topics = self.topics.lock().unwrap();
+        topics
+            .values()
+            .filter(|topic| topic.strong_count() > 0)
+            .count()
+    }
+}
+
+async fn poll<T, F, Fut>(
+    key: String,
+    interval: Duration,
+    sender: watch::Sender<Latest<T>>,
+    fetch: F,
+) where
+    T: Serialize,
+    F: Fn() -> Fut,
+    Fut: Future<Output = AppResult<Vec<T>>>,
+{
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        match fetch().await {
+            Ok(results) => {
+                let id = content_id(&results);
+                sender.send_if_modified(|latest| {
+                    if latest.as_ref().is_some_and(|snapshot| snapshot.id == id) {
+                        return false;
+                    }
+                    *latest = Some(Arc::new(Snapshot { id, results }));
+                    true
+                });
+            }
+            // Keep the last good snapshot and try again next time
+            Err(e) => warn!("Poll for {} failed: {}", key, e),
+        }
+    }
+}
+
+fn content_id<T: Serialize>(results: &[T]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_vec(results)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_subscribers_share_a_poller() {
+        let pollers = Pollers::new(Duration::from_millis(10));
+        let first_fetches = Arc::new(AtomicUsize::new(0));
+        let second_fetches = Arc::new(AtomicUsize::new(0));
+
+        let counter = first_fetches.clone();
+        let mut first = pollers.subscribe("940GZZLUVIC|victoria", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(vec![1, 2, 3]) }
+        });
+        let counter = second_fetches.clone();
+        let second = pollers.subscribe("940GZZLUVIC|victoria", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok(vec![4]) }
+        });
+
+        first.receiver.changed().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(first_fetches.load(Ordering::SeqCst) > 1);
+        assert_eq!(second_fetches.load(Ordering::SeqCst), 0);
+
+        // Unchanged results aren't republished
+        let snapshot = second.receiver.borrow().clone().unwrap();
+        assert_eq!(snapshot.results, vec![1, 2, 3]);
+        assert!(!first.receiver.has_changed().unwrap());
+
+        assert_eq!(pollers.active(), 1);
+        drop(first);
+        drop(second);
+        assert_eq!(pollers.active(), 0);
+    }
+}
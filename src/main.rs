@@ -1,17 +1,51 @@
+mod accessibility;
+mod accuracy;
+mod board;
+mod changes;
+#[cfg(feature = "arrow")]
+mod columnar;
+mod dataset;
 mod error;
+mod fare_zones;
+mod format;
+mod geo;
+mod gtfs;
+mod gtfs_rt;
+mod headways;
+mod live;
 mod models;
+mod pagination;
+mod positions;
+mod predictions;
+mod projection;
 mod routes;
+mod search;
+mod siri;
 mod tfl;
+mod webhooks;
 
 use axum::{http::Method, routing::get, Json, Router};
 use serde_json::json;
 use std::env;
-use tower_http::cors::{Any, CorsLayer};
+use std::sync::Arc;
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{Any, CorsLayer},
+};
 use tracing::info;
 
+use crate::accuracy::PredictionHistory;
+use crate::changes::DisruptionFeed;
+use crate::dataset::{Dataset, TUBE_LINES};
+use crate::predictions::{Freshness, FreshnessPolicy};
+use crate::routes::disruption::ALLOWED_MODES;
 use crate::routes::{
-    arrivals::arrivals_routes, disruption::disruption_routes, stations::stations_routes,
+    accessibility::accessibility_routes, analytics::analytics_routes, arrivals::arrivals_routes,
+    disruption::disruption_routes, gtfs::gtfs_routes, lines::lines_routes, live::live_routes,
+    stations::stations_routes, webhooks::webhook_routes,
 };
+use crate::tfl::TflClient;
+use crate::webhooks::Webhooks;
 
 #[tokio::main]
 async fn main() {
@@ -25,6 +59,41 @@ async fn main() {
     let port = env::var("PORT").unwrap_or_else(|_| "4000".to_string());
     let addr = format!("0.0.0.0:{}", port);
 
+    // Load the station dataset shared by the routers
+    let dataset = Arc::new(Dataset::load());
+
+    // `tb8-rs gtfs ...` exports a GTFS feed instead of serving
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("gtfs") {
+        if let Err(e) = gtfs::run_cli(&args[2..], &dataset).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Poll disruptions on every mode for the change feed and webhooks
+    let tfl_client = Arc::new(TflClient::new());
+    let modes = ALLOWED_MODES.iter().map(|mode| mode.to_string()).collect();
+    let feed = DisruptionFeed::start(tfl_client.clone(), modes, changes::POLL_INTERVAL);
+    let webhooks = Webhooks::start(tfl_client.clone(), feed.clone());
+
+    // Poll line predictions for the accuracy analytics; set TB8_ACCURACY_LINES empty
+    // to turn it off
+    let lines = match env::var("TB8_ACCURACY_LINES") {
+        Ok(lines) => lines
+            .split(',')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => TUBE_LINES.iter().map(|line| line.to_string()).collect(),
+    };
+    let history = PredictionHistory::start(tfl_client, lines, accuracy::POLL_INTERVAL);
+
+    // Stale prediction handling, shared so vehicles are tracked across endpoints
+    let freshness = Arc::new(Freshness::new(FreshnessPolicy::from_env()));
+
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -33,10 +102,18 @@ async fn main() {
 
     // Create the router with our routes
     let app = Router::new()
-        .merge(stations_routes())
-        .merge(arrivals_routes())
-        .merge(disruption_routes())
+        .merge(stations_routes(dataset.clone()))
+        .merge(arrivals_routes(dataset.clone(), freshness.clone()))
+        .merge(disruption_routes(feed))
+        .merge(accessibility_routes())
+        .merge(gtfs_routes(dataset.clone()))
+        .merge(lines_routes(dataset.clone(), freshness.clone()))
+        .merge(live_routes(dataset, freshness))
+        .merge(webhook_routes(webhooks))
+        .merge(analytics_routes(history))
         .route("/", get(root_handler))
+        // gzip, brotli or zstd, as negotiated by Accept-Encoding
+        .layer(CompressionLayer::new())
         .layer(cors);
 
     info!("Starting server on {}", addr);
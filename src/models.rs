@@ -1,5 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::fare_zones::FareZones;
+use crate::positions::TrainPosition;
+use crate::predictions::FreshnessPolicy;
 
 // Define core models equivalent to the Python Pydantic models
 
@@ -9,12 +14,32 @@ pub struct MetaData {
     pub response_time: DateTime<Utc>,
     pub response_latency: f64, // Duration in seconds
     pub query: String,
+    // For arrival predictions, the freshness policy applied and what it did
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freshness: Option<FreshnessReport>,
+    // For the disruption change feed, whether changes after `since` are missing:
+    // it's from before a restart or older than the oldest change kept
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FreshnessReport {
+    pub policy: FreshnessPolicy,
+    pub dropped: usize,
+    pub flagged: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Response<T> {
     pub context: MetaData,
     pub success: bool,
+    #[serde(default)]
+    pub total: usize, // Number of results before limit/offset
+    #[serde(default)]
+    pub next_cursor: Option<String>,
     pub results: Vec<T>,
 }
 
@@ -162,6 +187,144 @@ pub struct StopPoint {
     pub lat: Option<f64>,
     pub lon: Option<f64>,
     pub status: Option<bool>,
+    #[serde(default)]
+    #[serde(rename = "additionalProperties")]
+    pub additional_properties: Vec<AdditionalProperty>,
+    #[serde(default)]
+    pub children: Vec<StopPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdditionalProperty {
+    pub category: Option<String>,
+    pub key: Option<String>,
+    #[serde(rename = "sourceSystemKey")]
+    pub source_system_key: Option<String>,
+    pub value: Option<String>,
+}
+
+// Route sequence and timetable models
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MatchedStop {
+    pub id: Option<String>,
+    pub name: Option<String>,
+    #[serde(rename = "stationId")]
+    pub station_id: Option<String>,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<String>,
+    #[serde(rename = "topMostParentId")]
+    pub top_most_parent_id: Option<String>,
+    #[serde(rename = "stopType")]
+    pub stop_type: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderedRoute {
+    pub name: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "naptanIds")]
+    pub naptan_ids: Vec<String>,
+    #[serde(rename = "serviceType")]
+    pub service_type: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StopPointSequence {
+    #[serde(rename = "lineId")]
+    pub line_id: Option<String>,
+    #[serde(rename = "branchId")]
+    pub branch_id: Option<i32>,
+    pub direction: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "stopPoint")]
+    pub stop_point: Vec<MatchedStop>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteSequence {
+    #[serde(rename = "lineId")]
+    pub line_id: Option<String>,
+    #[serde(rename = "lineName")]
+    pub line_name: Option<String>,
+    pub direction: Option<String>,
+    pub mode: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "lineStrings")]
+    pub line_strings: Vec<String>,
+    #[serde(default)]
+    pub stations: Vec<MatchedStop>,
+    #[serde(default)]
+    #[serde(rename = "stopPointSequences")]
+    pub stop_point_sequences: Vec<StopPointSequence>,
+    #[serde(default)]
+    #[serde(rename = "orderedLineRoutes")]
+    pub ordered_line_routes: Vec<OrderedRoute>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimetableResponse {
+    #[serde(rename = "lineId")]
+    pub line_id: Option<String>,
+    #[serde(rename = "lineName")]
+    pub line_name: Option<String>,
+    pub direction: Option<String>,
+    #[serde(default)]
+    pub stations: Vec<MatchedStop>,
+    #[serde(default)]
+    pub stops: Vec<MatchedStop>,
+    pub timetable: Option<Timetable>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Timetable {
+    #[serde(rename = "departureStopId")]
+    pub departure_stop_id: Option<String>,
+    #[serde(default)]
+    pub routes: Vec<TimetableRoute>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimetableRoute {
+    #[serde(default)]
+    #[serde(rename = "stationIntervals")]
+    pub station_intervals: Vec<StationInterval>,
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StationInterval {
+    pub id: Option<String>,
+    #[serde(default)]
+    pub intervals: Vec<Interval>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Interval {
+    #[serde(rename = "stopId")]
+    pub stop_id: Option<String>,
+    // Minutes after departure from the timetable's departure stop
+    #[serde(rename = "timeToArrival")]
+    pub time_to_arrival: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Schedule {
+    pub name: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "knownJourneys")]
+    pub known_journeys: Vec<KnownJourney>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KnownJourney {
+    pub hour: Option<String>,
+    pub minute: Option<String>,
+    #[serde(rename = "intervalId")]
+    pub interval_id: Option<i32>,
 }
 
 // Arrival models
@@ -181,6 +344,11 @@ pub struct Prediction {
     pub line_id: Option<String>,
     #[serde(rename = "lineName")]
     pub line_name: Option<String>,
+    // Every line a train shared between lines was reported under, once merged
+    #[serde(default)]
+    #[serde(rename = "lineIds")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub line_ids: Vec<String>,
     #[serde(rename = "platformName")]
     pub platform_name: Option<String>,
     pub direction: Option<String>,
@@ -194,6 +362,10 @@ pub struct Prediction {
     pub time_to_station: Option<i32>,
     #[serde(rename = "currentLocation")]
     pub current_location: Option<String>,
+    // Parsed from current_location, with stations resolved to NaPTAN ids
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<TrainPosition>,
     pub towards: Option<String>,
     #[serde(rename = "expectedArrival")]
     pub expected_arrival: Option<DateTime<Utc>>,
@@ -202,6 +374,21 @@ pub struct Prediction {
     #[serde(rename = "modeName")]
     pub mode_name: Option<String>,
     pub timing: Option<PredictionTiming>,
+    // Why the freshness policy considers the prediction stale, when it flags them
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale: Option<StaleReason>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StaleReason {
+    // Past its time_to_live
+    Expired,
+    // timing.read is older than the policy allows
+    OldRead,
+    // The vehicle's time to this stop hasn't come down across recent polls
+    NotProgressing,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -215,6 +402,140 @@ pub struct PredictionTiming {
     // pub received: Option<NaiveDateTime>,
 }
 
+// Arrivals at a hub, grouped by mode then platform, each soonest first
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModeArrivals {
+    #[serde(rename = "modeName")]
+    pub mode_name: String,
+    pub platforms: Vec<PlatformArrivals>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlatformArrivals {
+    #[serde(rename = "platformName")]
+    pub platform_name: String,
+    pub arrivals: Vec<Prediction>,
+}
+
+// Where a train on a line is now, from the prediction for its next stop
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrainLocation {
+    #[serde(rename = "vehicleId")]
+    pub vehicle_id: String,
+    #[serde(rename = "lineId")]
+    pub line_id: Option<String>,
+    pub direction: Option<String>,
+    #[serde(rename = "destinationName")]
+    pub destination_name: Option<String>,
+    #[serde(rename = "currentLocation")]
+    pub current_location: Option<String>,
+    pub position: Option<TrainPosition>,
+    #[serde(rename = "nextStopNaptanId")]
+    pub next_stop_naptan_id: Option<String>,
+    #[serde(rename = "nextStopName")]
+    pub next_stop_name: Option<String>,
+    #[serde(rename = "timeToNextStop")]
+    pub time_to_next_stop: Option<i32>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
+// Observed gaps between consecutive trains at a station in one direction, in
+// seconds, from the expected arrivals of a line's predictions
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StationHeadways {
+    #[serde(rename = "naptanId")]
+    pub naptan_id: String,
+    #[serde(rename = "stationName")]
+    pub station_name: Option<String>,
+    // The prediction's direction, or its platform where TfL gives no direction
+    pub direction: String,
+    pub trains: usize,
+    #[serde(rename = "meanHeadway")]
+    pub mean_headway: f64,
+    #[serde(rename = "medianHeadway")]
+    pub median_headway: i64,
+    #[serde(rename = "p90Headway")]
+    pub p90_headway: i64,
+    #[serde(rename = "maxHeadway")]
+    pub max_headway: i64,
+    // Gaps longer than the threshold asked for
+    pub gaps: Vec<HeadwayGap>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HeadwayGap {
+    #[serde(rename = "afterVehicleId")]
+    pub after_vehicle_id: Option<String>,
+    #[serde(rename = "vehicleId")]
+    pub vehicle_id: Option<String>,
+    #[serde(rename = "expectedArrival")]
+    pub expected_arrival: DateTime<Utc>,
+    pub headway: i64,
+}
+
+// How far off a line's predictions were, for predictions made a given time ahead.
+// Errors are in seconds, positive when the train arrived later than predicted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PredictionAccuracy {
+    #[serde(rename = "lineId")]
+    pub line_id: String,
+    pub horizon: String,
+    // Seconds to arrival when the predictions were made, up to but excluding
+    // `horizonTo`
+    #[serde(rename = "horizonFrom")]
+    pub horizon_from: i64,
+    #[serde(rename = "horizonTo")]
+    pub horizon_to: Option<i64>,
+    pub samples: usize,
+    pub arrivals: usize,
+    #[serde(rename = "meanError")]
+    pub mean_error: f64,
+    #[serde(rename = "meanAbsoluteError")]
+    pub mean_absolute_error: f64,
+    #[serde(rename = "p90AbsoluteError")]
+    pub p90_absolute_error: i64,
+    // Share of predictions within a minute of the arrival
+    #[serde(rename = "withinMinute")]
+    pub within_minute: f64,
+}
+
+// A station's departure board: the next departures from each platform, grouped by
+// destination
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlatformBoard {
+    #[serde(rename = "stationName")]
+    pub station_name: Option<String>,
+    #[serde(rename = "platformName")]
+    pub platform_name: String,
+    pub destinations: Vec<DestinationDepartures>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DestinationDepartures {
+    #[serde(rename = "destinationName")]
+    pub destination_name: String,
+    pub departures: Vec<Departure>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Departure {
+    #[serde(rename = "lineId")]
+    pub line_id: Option<String>,
+    #[serde(rename = "lineName")]
+    pub line_name: Option<String>,
+    #[serde(rename = "vehicleId")]
+    pub vehicle_id: Option<String>,
+    #[serde(rename = "timeToStation")]
+    pub time_to_station: i32,
+    // "due" or "2 min", as shown on station displays
+    pub due: String,
+    #[serde(rename = "expectedArrival")]
+    pub expected_arrival: Option<DateTime<Utc>>,
+    #[serde(rename = "currentLocation")]
+    pub current_location: Option<String>,
+}
+
 // Station models
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -224,7 +545,7 @@ pub struct Station {
     #[serde(rename = "stationName")]
     pub station_name: String,
     #[serde(rename = "fareZones")]
-    pub fare_zones: Option<String>,
+    pub fare_zones: Option<FareZones>,
     #[serde(rename = "hubNaptanCode")]
     pub hub_naptan_code: Option<String>,
     pub wifi: Option<bool>,
@@ -252,3 +573,116 @@ pub struct StationPoint {
     #[serde(rename = "friendlyName")]
     pub friendly_name: String,
 }
+
+// Station search models
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StationMatch {
+    #[serde(rename = "naptanId")]
+    pub naptan_id: String,
+    pub score: f64,
+    #[serde(rename = "matchedOn")]
+    pub matched_on: String,
+    #[serde(flatten)]
+    pub station: Station,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NearbyStation {
+    // Haversine distance from the query point, in metres
+    pub distance: f64,
+    #[serde(flatten)]
+    pub station: Station,
+}
+
+// Accessibility models
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LiftDisruption {
+    #[serde(rename = "icsCode")]
+    pub ics_code: Option<String>,
+    #[serde(rename = "naptanCode")]
+    pub naptan_code: Option<String>,
+    #[serde(rename = "stopPointName")]
+    pub stop_point_name: Option<String>,
+    #[serde(rename = "outageStartArea")]
+    pub outage_start_area: Option<String>,
+    #[serde(rename = "outageEndArea")]
+    pub outage_end_area: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StepFreeAccess {
+    ToTrain,
+    ToPlatform,
+    Partial,
+    None,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StationAccessibility {
+    #[serde(rename = "naptanId")]
+    pub naptan_id: String,
+    #[serde(rename = "commonName")]
+    pub common_name: Option<String>,
+    #[serde(rename = "stepFree")]
+    pub step_free: StepFreeAccess,
+    #[serde(rename = "accessViaLift")]
+    pub access_via_lift: Option<String>,
+    #[serde(rename = "accessibilitySummary")]
+    pub accessibility_summary: Option<String>,
+    // All properties in the "Accessibility" category, keyed by property key
+    pub properties: BTreeMap<String, String>,
+    #[serde(rename = "liftDisruptions")]
+    pub lift_disruptions: Vec<LiftDisruption>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    Added,
+    Updated,
+    Resolved,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DisruptionChange {
+    // `<epoch>-<sequence>`, for resuming the feed with `since=` or `Last-Event-ID`
+    pub id: String,
+    // Increases by one per change, starting again when the server restarts
+    pub sequence: u64,
+    pub kind: ChangeKind,
+    pub mode: String,
+    #[serde(rename = "disruptionId")]
+    pub disruption_id: String,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: DateTime<Utc>,
+    // The disruption as last seen, for resolved changes
+    pub disruption: Disruption,
+    // Whether the change comes from the first snapshot of its mode since startup,
+    // so reports a disruption that was already current rather than a new one
+    #[serde(skip)]
+    pub baseline: bool,
+}
+
+// A change in a line's headline status between polls
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusChange {
+    #[serde(rename = "lineId")]
+    pub line_id: String,
+    #[serde(rename = "lineName")]
+    pub line_name: String,
+    pub mode: String,
+    #[serde(rename = "statusSeverity")]
+    pub status_severity: Option<i32>,
+    #[serde(rename = "statusSeverityDescription")]
+    pub status_severity_description: Option<String>,
+    pub reason: Option<String>,
+    #[serde(rename = "previousStatusSeverityDescription")]
+    pub previous_status_severity_description: Option<String>,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: DateTime<Utc>,
+}
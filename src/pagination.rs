@@ -0,0 +1,344 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use crate::error::{AppError, AppResult};
+use crate::models::Response;
+
+// Sorting and paging of results: `sort=` by one or more dotted serialized field
+// names, `order=asc|desc`, then `offset=` (or an opaque `cursor=` from a previous
+// page's `next_cursor`) and `limit=`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pagination {
+    sort: Vec<Vec<String>>,
+    descending: bool,
+    offset: usize,
+    after: Option<Keyset>,
+    limit: Option<usize>,
+}
+
+// Where a page of sorted results ends, so the next page starts after the same
+// result even if others have come or gone before it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Keyset {
+    // The `sort=` and `order=` the page was fetched with, as the keys mean
+    // nothing in any other order
+    sort: Vec<Vec<String>>,
+    descending: bool,
+    // The last result's sort keys
+    keys: Vec<Value>,
+    // How many results with exactly those keys have been returned, as keys
+    // needn't be unique
+    seen: usize,
+}
+
+impl Pagination {
+    pub fn new(
+        sort: Option<&str>,
+        order: Option<&str>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+        cursor: Option<&str>,
+    ) -> AppResult<Self> {
+        let descending = match order.map(str::to_ascii_lowercase).as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => {
+                return Err(AppError::ParseError(format!(
+                    "Invalid order: {} (expected asc or desc)",
+                    other
+                )))
+            }
+        };
+        if limit == Some(0) {
+            return Err(AppError::ParseError(
+                "Invalid limit: 0 (expected at least 1)".to_string(),
+            ));
+        }
+        let sort: Vec<Vec<String>> = sort
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| field.split('.').map(str::to_string).collect())
+            .collect();
+        let (offset, after) = match cursor {
+            Some(cursor) => decode_cursor(cursor)?,
+            None => (offset.unwrap_or_default(), None),
+        };
+        if let Some(after) = &after {
+            if after.sort != sort || after.descending != descending {
+                return Err(AppError::ParseError(format!(
+                    "Invalid cursor: {} (from a different sort or order)",
+                    cursor.unwrap_or_default()
+                )));
+            }
+        }
+
+        Ok(Self {
+            sort,
+            descending,
+            offset,
+            after,
+            limit,
+        })
+    }
+
+    // Whether anything was asked for, so results must all be in hand first
+    pub fn is_empty(&self) -> bool {
+        self.sort.is_empty() && self.offset == 0 && self.after.is_none() && self.limit.is_none()
+    }
+
+    // Sort (by the requested fields, or the type's default order) then page the
+    // results, recording the total and a cursor for the next page
+    pub fn apply<T: Serialize>(
+        &self,
+        mut response: Response<T>,
+        default_sort: Option<&str>,
+    ) -> Response<T> {
+        let sort: Vec<Vec<String>> = if self.sort.is_empty() {
+            default_sort
+                .map(|field| vec![field.split('.').map(str::to_string).collect()])
+                .unwrap_or_default()
+        } else {
+            self.sort.clone()
+        };
+
+        let mut keys: Vec<Vec<Value>> = Vec::new();
+        if !sort.is_empty() {
+            let mut keyed: Vec<(Vec<Value>, T)> = response
+                .results
+                .into_iter()
+                .map(|result| {
+                    let value = serde_json::to_value(&result).unwrap_or(Value::Null);
+                    let keys = sort.iter().map(|path| lookup(&value, path)).collect();
+                    (keys, result)
+                })
+                .collect();
+            keyed.sort_by(|(a, _), (b, _)| self.compare_keys(a, b));
+            (keys, response.results) = keyed.into_iter().unzip();
+        }
+
+        let total = response.results.len();
+        let start = match &self.after {
+            Some(after) => {
+                let before = keys.partition_point(|k| self.compare_keys(k, &after.keys).is_lt());
+                let equal = keys[before..]
+                    .iter()
+                    .take_while(|k| self.compare_keys(k, &after.keys).is_eq())
+                    .count();
+                before + after.seen.min(equal)
+            }
+            None => self.offset.min(total),
+        };
+        let end = self
+            .limit
+            .map_or(total, |limit| start.saturating_add(limit).min(total));
+        response.results = response
+            .results
+            .into_iter()
+            .skip(start)
+            .take(end - start)
+            .collect();
+        response.total = total;
+        let last = end.checked_sub(1).and_then(|last| keys.get(last));
+        response.next_cursor = (end < total).then(|| match last {
+            Some(last) => {
+                let first = keys.partition_point(|k| self.compare_keys(k, last).is_lt());
+                encode_keyset(&Keyset {
+                    sort: self.sort.clone(),
+                    descending: self.descending,
+                    keys: last.clone(),
+                    seen: end - first,
+                })
+            }
+            None => encode_offset(end),
+        });
+        response
+    }
+
+    fn compare_keys(&self, a: &[Value], b: &[Value]) -> Ordering {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b, self.descending))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+fn lookup(value: &Value, path: &[String]) -> Value {
+    path.iter()
+        .try_fold(value, |value, key| value.get(key))
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+// Numbers numerically, everything else by its JSON value (RFC 3339 timestamps sort
+// correctly as strings). Missing values always sort last.
+fn compare(a: &Value, b: &Value, descending: bool) -> Ordering {
+    let ordering = match (a, b) {
+        (Value::Null, Value::Null) => return Ordering::Equal,
+        (Value::Null, _) => return Ordering::Greater,
+        (_, Value::Null) => return Ordering::Less,
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .unwrap_or_default()
+            .total_cmp(&b.as_f64().unwrap_or_default()),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (a, b) => a.to_string().cmp(&b.to_string()),
+    };
+    if descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+// Cursors are opaque to clients: the last result's sort keys for sorted results,
+// otherwise the next page's offset
+fn encode_offset(offset: usize) -> String {
+    format!("o{:x}", offset)
+}
+
+fn encode_keyset(keyset: &Keyset) -> String {
+    let json = serde_json::to_vec(keyset).unwrap_or_default();
+    let hex: String = json.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("k{}", hex)
+}
+
+fn decode_cursor(cursor: &str) -> AppResult<(usize, Option<Keyset>)> {
+    let invalid = || AppError::ParseError(format!("Invalid cursor: {}", cursor));
+
+    if let Some(offset) = cursor.strip_prefix('o') {
+        let offset = usize::from_str_radix(offset, 16).map_err(|_| invalid())?;
+        return Ok((offset, None));
+    }
+    let hex = cursor.strip_prefix('k').ok_or_else(invalid)?;
+    let json = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let keyset = serde_json::from_slice(&json).map_err(|_| invalid())?;
+    Ok((0, Some(keyset)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::create_response;
+    use serde_json::json;
+    use std::time::Instant;
+
+    fn arrivals() -> Response<Value> {
+        create_response(
+            Instant::now(),
+            "victoria",
+            vec![
+                json!({ "id": "a", "timeToStation": 300, "timing": { "read": "2024-01-01T12:00:02Z" } }),
+                json!({ "id": "b", "timeToStation": 60, "timing": { "read": "2024-01-01T12:00:01Z" } }),
+                json!({ "id": "c", "timing": { "read": "2024-01-01T12:00:03Z" } }),
+                json!({ "id": "d", "timeToStation": 120 }),
+            ],
+        )
+    }
+
+    fn ids(response: &Response<Value>) -> Vec<&str> {
+        response
+            .results
+            .iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_and_page() {
+        let pagination = Pagination::new(None, None, None, Some(2), None).unwrap();
+        let page = pagination.apply(arrivals(), Some("timeToStation"));
+        assert_eq!(ids(&page), ["b", "d"]);
+        assert_eq!(page.total, 4);
+
+        let cursor = page.next_cursor.unwrap();
+        let pagination = Pagination::new(None, None, None, Some(2), Some(&cursor)).unwrap();
+        let page = pagination.apply(arrivals(), Some("timeToStation"));
+        assert_eq!(ids(&page), ["a", "c"]);
+        assert_eq!(page.next_cursor, None);
+
+        // Unsorted results page by offset
+        let pagination = Pagination::new(None, None, None, Some(3), None).unwrap();
+        let page = pagination.apply(arrivals(), None);
+        assert_eq!(ids(&page), ["a", "b", "c"]);
+        assert_eq!(page.next_cursor.as_deref(), Some("o3"));
+
+        let pagination =
+            Pagination::new(Some("timing.read"), Some("desc"), None, None, None).unwrap();
+        assert_eq!(
+            ids(&pagination.apply(arrivals(), None)),
+            ["c", "a", "b", "d"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_paging() {
+        assert!(Pagination::new(None, Some("sideways"), None, None, None).is_err());
+        assert!(Pagination::new(None, None, None, None, Some("bogus")).is_err());
+        assert!(Pagination::new(None, None, None, None, Some("k7b")).is_err());
+        assert!(Pagination::new(None, None, None, Some(0), None).is_err());
+    }
+
+    #[test]
+    fn test_cursor_survives_changes() {
+        let pagination = Pagination::new(None, None, None, Some(2), None).unwrap();
+        let page = pagination.apply(arrivals(), Some("timeToStation"));
+        assert_eq!(ids(&page), ["b", "d"]);
+
+        // The first train has left by the time the next page is fetched
+        let mut changed = arrivals();
+        changed.results.retain(|r| r["id"] != "b");
+        let cursor = page.next_cursor.unwrap();
+        let pagination = Pagination::new(None, None, None, Some(2), Some(&cursor)).unwrap();
+        let page = pagination.apply(changed, Some("timeToStation"));
+        assert_eq!(ids(&page), ["a", "c"]);
+    }
+
+    #[test]
+    fn test_cursor_with_equal_keys() {
+        let results = || {
+            create_response(
+                Instant::now(),
+                "",
+                vec![
+                    json!({ "id": "a", "platform": 1 }),
+                    json!({ "id": "b", "platform": 1 }),
+                    json!({ "id": "c", "platform": 1 }),
+                ],
+            )
+        };
+
+        let pagination = Pagination::new(Some("platform"), None, None, Some(2), None).unwrap();
+        let page = pagination.apply(results(), None);
+        assert_eq!(ids(&page), ["a", "b"]);
+
+        let cursor = page.next_cursor.unwrap();
+        let pagination =
+            Pagination::new(Some("platform"), None, None, Some(2), Some(&cursor)).unwrap();
+        let page = pagination.apply(results(), None);
+        assert_eq!(ids(&page), ["c"]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_cursor_from_another_order() {
+        let pagination = Pagination::new(None, None, None, Some(2), None).unwrap();
+        let page = pagination.apply(arrivals(), Some("timeToStation"));
+        let cursor = page.next_cursor.unwrap();
+
+        assert!(Pagination::new(None, Some("desc"), None, Some(2), Some(&cursor)).is_err());
+        assert!(Pagination::new(Some("timing.read"), None, None, Some(2), Some(&cursor)).is_err());
+        assert!(Pagination::new(None, Some("asc"), None, Some(2), Some(&cursor)).is_ok());
+    }
+}
@@ -0,0 +1,342 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::dataset::Dataset;
+use crate::models::{Prediction, Station};
+
+// Train positions from TfL's free-text `currentLocation` ("Between Oval and
+// Stockwell", "At Brixton Platform 2", "Approaching Victoria"), with the stations
+// named resolved to NaPTAN ids through the station dataset.
+
+// Search scores below this don't count as naming a station
+const MIN_RESOLVE_SCORE: f64 = 0.85;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StationRef {
+    #[serde(rename = "stationName")]
+    pub station_name: String,
+    #[serde(rename = "naptanId")]
+    pub naptan_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TrainPosition {
+    At {
+        station: StationRef,
+        platform: Option<String>,
+    },
+    Approaching {
+        station: StationRef,
+    },
+    Leaving {
+        station: StationRef,
+    },
+    Between {
+        from: StationRef,
+        to: StationRef,
+    },
+    // "Near Oval", or "North of Oval" with a direction
+    Near {
+        station: StationRef,
+        direction: Option<String>,
+    },
+    Unknown {
+        text: String,
+    },
+}
+
+impl TrainPosition {
+    // The stations the position is at or between
+    pub fn stations(&self) -> Vec<&StationRef> {
+        match self {
+            TrainPosition::At { station, .. }
+            | TrainPosition::Approaching { station }
+            | TrainPosition::Leaving { station }
+            | TrainPosition::Near { station, .. } => vec![station],
+            TrainPosition::Between { from, to } => vec![from, to],
+            TrainPosition::Unknown { .. } => Vec::new(),
+        }
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| text[prefix.len()..].trim())
+        .filter(|rest| !rest.is_empty())
+}
+
+// Parse a location, naming stations with `resolve`, which gives a NaPTAN id for a
+// station name if it knows one. It also settles ambiguous splits such as "Between
+// Elephant and Castle and Kennington".
+pub fn parse(text: &str, resolve: impl Fn(&str) -> Option<String>) -> Option<TrainPosition> {
+    let text = text.trim().trim_end_matches('.');
+    if text.is_empty() {
+        return None;
+    }
+    let station = |name: &str| StationRef {
+        station_name: name.trim().to_string(),
+        naptan_id: resolve(name.trim()),
+    };
+
+    if let Some(rest) = strip_prefix_ignore_case(text, "Between ") {
+        let splits: Vec<(&str, &str)> = rest
+            .match_indices(" and ")
+            .map(|(i, separator)| (&rest[..i], &rest[i + separator.len()..]))
+            .collect();
+        let (from, to) = splits
+            .iter()
+            .find(|(from, to)| resolve(from).is_some() && resolve(to).is_some())
+            .or(splits.first())?;
+        return Some(TrainPosition::Between {
+            from: station(from),
+            to: station(to),
+        });
+    }
+    if let Some(rest) = strip_prefix_ignore_case(text, "Approaching ") {
+        return Some(TrainPosition::Approaching {
+            station: station(rest),
+        });
+    }
+    for prefix in ["Leaving ", "Left ", "Departed ", "Departing "] {
+        if let Some(rest) = strip_prefix_ignore_case(text, prefix) {
+            return Some(TrainPosition::Leaving {
+                station: station(rest),
+            });
+        }
+    }
+    if let Some(rest) = strip_prefix_ignore_case(text, "Near ") {
+        return Some(TrainPosition::Near {
+            station: station(rest),
+            direction: None,
+        });
+    }
+    for direction in ["North", "South", "East", "West"] {
+        if let Some(rest) = strip_prefix_ignore_case(text, &format!("{} of ", direction)) {
+            return Some(TrainPosition::Near {
+                station: station(rest),
+                direction: Some(direction.to_lowercase()),
+            });
+        }
+    }
+    if let Some(rest) = strip_prefix_ignore_case(text, "At ") {
+        let (name, platform) = match rest.to_ascii_lowercase().rfind(" platform ") {
+            Some(i) => (&rest[..i], Some(rest[i + " platform ".len()..].trim())),
+            None => (rest, None),
+        };
+        return Some(TrainPosition::At {
+            station: station(name),
+            platform: platform.map(str::to_string),
+        });
+    }
+
+    Some(TrainPosition::Unknown {
+        text: text.to_string(),
+    })
+}
+
+// A station named in a location, preferring stations on the train's line
+pub fn resolve_station<'a>(
+    dataset: &'a Dataset,
+    name: &str,
+    line_id: Option<&str>,
+) -> Option<&'a Station> {
+    let matches = dataset.search.search(&dataset.stations, name, 5);
+    let candidates: Vec<&Station> = matches
+        .iter()
+        .filter(|m| m.score >= MIN_RESOLVE_SCORE)
+        .filter_map(|m| {
+            dataset
+                .stations
+                .iter()
+                .find(|s| s.station_unique_id == m.naptan_id)
+        })
+        .collect();
+
+    candidates
+        .iter()
+        .find(|station| {
+            line_id.is_some_and(|line_id| {
+                station
+                    .lines
+                    .as_ref()
+                    .is_some_and(|lines| lines.iter().any(|l| l == line_id))
+            })
+        })
+        .or(candidates.first())
+        .copied()
+}
+
+// Station names already resolved, by name and line, to their NaPTAN ids
+type Resolved = RefCell<HashMap<(String, Option<String>), Option<String>>>;
+
+// The position of the train a prediction is for. "At Platform" means the
+// prediction's own station.
+pub fn locate(dataset: &Dataset, prediction: &Prediction) -> Option<TrainPosition> {
+    locate_with(dataset, prediction, &Resolved::default())
+}
+
+// As `locate`, looking each station name up only once. Trains on a line mostly
+// report the same few places, so this saves most of the fuzzy searches.
+pub fn locate_all(dataset: &Dataset, predictions: &mut [Prediction]) {
+    let resolved = Resolved::default();
+    for prediction in predictions {
+        prediction.position = locate_with(dataset, prediction, &resolved);
+    }
+}
+
+fn locate_with(
+    dataset: &Dataset,
+    prediction: &Prediction,
+    resolved: &Resolved,
+) -> Option<TrainPosition> {
+    let text = prediction.current_location.as_deref()?;
+    if text.trim().eq_ignore_ascii_case("At Platform") {
+        return Some(TrainPosition::At {
+            station: StationRef {
+                station_name: prediction.station_name.clone().unwrap_or_default(),
+                naptan_id: prediction.naptan_id.clone(),
+            },
+            platform: prediction.platform_name.clone(),
+        });
+    }
+
+    let line_id = prediction.line_id.as_deref();
+    parse(text, |name| {
+        let key = (name.to_string(), line_id.map(str::to_string));
+        if let Some(naptan_id) = resolved.borrow().get(&key) {
+            return naptan_id.clone();
+        }
+        let naptan_id =
+            resolve_station(dataset, name, line_id).map(|s| s.station_unique_id.clone());
+        resolved.borrow_mut().insert(key, naptan_id.clone());
+        naptan_id
+    })
+}
+
+// Where a position is on the map: the station, or halfway between two
+pub fn coordinates(dataset: &Dataset, position: &TrainPosition) -> Option<(f64, f64)> {
+    let points: Vec<(f64, f64)> = position
+        .stations()
+        .iter()
+        .map(|station| {
+            let naptan_id = station.naptan_id.as_deref()?;
+            let station = dataset
+                .stations
+                .iter()
+                .find(|s| s.station_unique_id == naptan_id)?;
+            Some((station.lat?, station.lon?))
+        })
+        .collect::<Option<_>>()?;
+    if points.is_empty() {
+        return None;
+    }
+
+    let count = points.len() as f64;
+    let (lat, lon) = points.iter().fold((0.0, 0.0), |(lat, lon), point| {
+        (lat + point.0, lon + point.1)
+    });
+    Some((lat / count, lon / count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(name: &str) -> Option<String> {
+        match name {
+            "Oval" => Some("940GZZLUOVL".to_string()),
+            "Stockwell" => Some("940GZZLUSKW".to_string()),
+            "Elephant and Castle" => Some("940GZZLUEAC".to_string()),
+            "Kennington" => Some("940GZZLUKNG".to_string()),
+            "Brixton" => Some("940GZZLUBXN".to_string()),
+            _ => None,
+        }
+    }
+
+    fn station(name: &str) -> StationRef {
+        StationRef {
+            station_name: name.to_string(),
+            naptan_id: resolve(name),
+        }
+    }
+
+    #[test]
+    fn test_parse_positions() {
+        assert_eq!(
+            parse("Between Oval and Stockwell", resolve),
+            Some(TrainPosition::Between {
+                from: station("Oval"),
+                to: station("Stockwell"),
+            })
+        );
+        assert_eq!(
+            parse("Between Elephant and Castle and Kennington", resolve),
+            Some(TrainPosition::Between {
+                from: station("Elephant and Castle"),
+                to: station("Kennington"),
+            })
+        );
+        assert_eq!(
+            parse("At Brixton Platform 2", resolve),
+            Some(TrainPosition::At {
+                station: station("Brixton"),
+                platform: Some("2".to_string()),
+            })
+        );
+        assert_eq!(
+            parse("Approaching Victoria", resolve),
+            Some(TrainPosition::Approaching {
+                station: station("Victoria"),
+            })
+        );
+        assert_eq!(
+            parse("North of Stockwell", resolve),
+            Some(TrainPosition::Near {
+                station: station("Stockwell"),
+                direction: Some("north".to_string()),
+            })
+        );
+        assert_eq!(
+            parse("Left Oval", resolve),
+            Some(TrainPosition::Leaving {
+                station: station("Oval"),
+            })
+        );
+        assert_eq!(
+            parse("In Brixton Sidings", resolve),
+            Some(TrainPosition::Unknown {
+                text: "In Brixton Sidings".to_string(),
+            })
+        );
+        assert_eq!(parse(" ", resolve), None);
+    }
+
+    #[test]
+    fn test_resolve_against_dataset() {
+        let dataset = Dataset::load();
+        let station = &dataset.stations[0];
+        let prediction: Prediction = serde_json::from_value(serde_json::json!({
+            "currentLocation": format!("Approaching {}", station.station_name),
+        }))
+        .unwrap();
+
+        let position = locate(&dataset, &prediction).unwrap();
+        assert_eq!(
+            position.stations()[0].naptan_id.as_deref(),
+            Some(station.station_unique_id.as_str())
+        );
+        assert_eq!(
+            coordinates(&dataset, &position),
+            Some((station.lat.unwrap(), station.lon.unwrap()))
+        );
+
+        // Sharing lookups between trains places them the same
+        let mut predictions = vec![prediction.clone(), prediction];
+        locate_all(&dataset, &mut predictions);
+        assert_eq!(predictions[0].position, Some(position.clone()));
+        assert_eq!(predictions[1].position, Some(position));
+    }
+}
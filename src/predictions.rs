@@ -0,0 +1,342 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::models::{FreshnessReport, Prediction, StaleReason};
+
+// Clean-up of raw arrival predictions before they're served.
+
+// How far apart two lines' expected arrivals for the same train at the same stop
+// can be and still be merged
+const SHARED_TRAIN_TOLERANCE: Duration = Duration::seconds(30);
+
+// Merge predictions for one train at one stop that TfL reports under several lines,
+// as it does on shared track (Circle and Hammersmith & City, say). Predictions match
+// on vehicle, stop, platform and direction with expected arrivals within the
+// tolerance, since TfL sometimes gives unrelated trains the same placeholder
+// vehicle id; the merged
+// prediction keeps the earliest's fields and lists every line in `line_ids`.
+// Results come out soonest first.
+pub fn merge_shared(mut predictions: Vec<Prediction>) -> Vec<Prediction> {
+    predictions.sort_by(|a, b| {
+        (
+            &a.vehicle_id,
+            &a.naptan_id,
+            &a.platform_name,
+            &a.direction,
+            a.expected_arrival,
+        )
+            .cmp(&(
+                &b.vehicle_id,
+                &b.naptan_id,
+                &b.platform_name,
+                &b.direction,
+                b.expected_arrival,
+            ))
+    });
+
+    let mut merged: Vec<Prediction> = Vec::with_capacity(predictions.len());
+    for mut prediction in predictions {
+        if let Some(line_id) = &prediction.line_id {
+            if !prediction.line_ids.contains(line_id) {
+                prediction.line_ids.insert(0, line_id.clone());
+            }
+        }
+
+        match merged.last_mut() {
+            Some(previous) if same_train(previous, &prediction) => {
+                for line_id in prediction.line_ids {
+                    if !previous.line_ids.contains(&line_id) {
+                        previous.line_ids.push(line_id);
+                    }
+                }
+            }
+            _ => merged.push(prediction),
+        }
+    }
+
+    merged.sort_by_key(|p| p.time_to_station.unwrap_or(i32::MAX));
+    merged
+}
+
+fn same_train(a: &Prediction, b: &Prediction) -> bool {
+    let (Some(vehicle), Some(stop)) = (&a.vehicle_id, &a.naptan_id) else {
+        return false;
+    };
+    let close = match (a.expected_arrival, b.expected_arrival) {
+        (Some(a), Some(b)) => (a - b).abs() <= SHARED_TRAIN_TOLERANCE,
+        _ => false,
+    };
+    b.vehicle_id.as_ref() == Some(vehicle)
+        && b.naptan_id.as_ref() == Some(stop)
+        && a.platform_name == b.platform_name
+        && a.direction == b.direction
+        && close
+}
+
+// What to do with stale predictions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StaleAction {
+    Drop,
+    #[default]
+    Flag,
+    Keep,
+}
+
+// When a prediction counts as stale: past its time_to_live, read from TfL's
+// sources more than `max_read_age` seconds ago, or for a vehicle whose time to the
+// stop hasn't come down for `stall_after` seconds of polling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FreshnessPolicy {
+    pub action: StaleAction,
+    #[serde(rename = "maxReadAge")]
+    pub max_read_age: i64,
+    #[serde(rename = "stallAfter")]
+    pub stall_after: i64,
+}
+
+impl Default for FreshnessPolicy {
+    fn default() -> Self {
+        Self {
+            action: StaleAction::default(),
+            max_read_age: 120,
+            stall_after: 180,
+        }
+    }
+}
+
+impl FreshnessPolicy {
+    // The defaults, overridden by TB8_STALE_PREDICTIONS (drop, flag or keep),
+    // TB8_MAX_PREDICTION_AGE and TB8_STALL_AFTER (seconds)
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Ok(action) = env::var("TB8_STALE_PREDICTIONS") {
+            match serde_json::from_value(serde_json::Value::String(action.to_lowercase())) {
+                Ok(action) => policy.action = action,
+                Err(_) => warn!("Ignoring TB8_STALE_PREDICTIONS={}", action),
+            }
+        }
+        for (name, seconds) in [
+            ("TB8_MAX_PREDICTION_AGE", &mut policy.max_read_age),
+            ("TB8_STALL_AFTER", &mut policy.stall_after),
+        ] {
+            if let Ok(value) = env::var(name) {
+                match value.parse() {
+                    Ok(value) => *seconds = value,
+                    Err(_) => warn!("Ignoring {}={}", name, value),
+                }
+            }
+        }
+        info!("Prediction freshness policy: {:?}", policy);
+        policy
+    }
+}
+
+// Forget vehicles at a stop that haven't been seen for this long
+const PROGRESS_RETENTION: Duration = Duration::minutes(15);
+
+struct Progress {
+    time_to_station: i32,
+    // When the time to station last came down (or was first seen)
+    since: DateTime<Utc>,
+    seen: DateTime<Utc>,
+}
+
+// Applies the freshness policy, remembering each vehicle's progress towards each
+// stop across the polls of every endpoint that serves predictions
+pub struct Freshness {
+    pub policy: FreshnessPolicy,
+    progress: Mutex<HashMap<(String, String), Progress>>,
+}
+
+impl Freshness {
+    pub fn new(policy: FreshnessPolicy) -> Self {
+        Self {
+            policy,
+            progress: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn apply(
+        &self,
+        predictions: Vec<Prediction>,
+        now: DateTime<Utc>,
+    ) -> (Vec<Prediction>, FreshnessReport) {
+        let mut report = FreshnessReport {
+            policy: self.policy.clone(),
+            dropped: 0,
+            flagged: 0,
+        };
+
+        let mut kept = Vec::with_capacity(predictions.len());
+        for mut prediction in predictions {
+            let reason = self.stale_reason(&prediction, now);
+            match (reason, self.policy.action) {
+                (Some(_), StaleAction::Drop) => report.dropped += 1,
+                (Some(reason), StaleAction::Flag) => {
+                    prediction.stale = Some(reason);
+                    report.flagged += 1;
+                    kept.push(prediction);
+                }
+                _ => kept.push(prediction),
+            }
+        }
+
+        let mut progress = self.progress.lock().unwrap();
+        progress.retain(|_, p| now - p.seen < PROGRESS_RETENTION);
+        (kept, report)
+    }
+
+    fn stale_reason(&self, prediction: &Prediction, now: DateTime<Utc>) -> Option<StaleReason> {
+        let not_progressing = self.observe(prediction, now);
+        if prediction.time_to_live.is_some_and(|ttl| ttl < now) {
+            Some(StaleReason::Expired)
+        } else if prediction
+            .timing
+            .as_ref()
+            .and_then(|timing| timing.read)
+            .is_some_and(|read| now - read > Duration::seconds(self.policy.max_read_age))
+        {
+            Some(StaleReason::OldRead)
+        } else if not_progressing {
+            Some(StaleReason::NotProgressing)
+        } else {
+            None
+        }
+    }
+
+    // Record the vehicle's time to the stop, and whether it has stalled. A train
+    // waiting at the platform (no time left to come down) isn't stalled.
+    fn observe(&self, prediction: &Prediction, now: DateTime<Utc>) -> bool {
+        let (Some(vehicle_id), Some(naptan_id), Some(time_to_station)) = (
+            &prediction.vehicle_id,
+            &prediction.naptan_id,
+            prediction.time_to_station,
+        ) else {
+            return false;
+        };
+
+        let mut progress = self.progress.lock().unwrap();
+        let entry = progress
+            .entry((vehicle_id.clone(), naptan_id.clone()))
+            .or_insert(Progress {
+                time_to_station,
+                since: now,
+                seen: now,
+            });
+        if time_to_station < entry.time_to_station {
+            entry.since = now;
+        }
+        entry.time_to_station = time_to_station;
+        entry.seen = now;
+
+        time_to_station > 0 && now - entry.since >= Duration::seconds(self.policy.stall_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn prediction(line_id: &str, vehicle_id: &str, expected_arrival: &str) -> Prediction {
+        serde_json::from_value(json!({
+            "lineId": line_id,
+            "vehicleId": vehicle_id,
+            "naptanId": "940GZZLUBST",
+            "expectedArrival": expected_arrival,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_shared() {
+        let merged = merge_shared(vec![
+            prediction("circle", "204", "2024-01-01T12:03:00Z"),
+            prediction("hammersmith-city", "204", "2024-01-01T12:03:20Z"),
+            prediction("metropolitan", "204", "2024-01-01T12:03:10Z"),
+            // Same vehicle number, but a later trip
+            prediction("circle", "204", "2024-01-01T12:40:00Z"),
+            prediction("circle", "215", "2024-01-01T12:03:00Z"),
+            // A placeholder vehicle id for a train on the other platform
+            {
+                let mut other = prediction("circle", "215", "2024-01-01T12:03:05Z");
+                other.platform_name = Some("Westbound - Platform 2".to_string());
+                other.direction = Some("outbound".to_string());
+                other
+            },
+        ]);
+
+        assert_eq!(merged.len(), 4);
+        let shared = merged
+            .iter()
+            .find(|p| p.line_ids.len() > 1)
+            .expect("merged prediction");
+        assert_eq!(shared.line_id.as_deref(), Some("circle"));
+        assert_eq!(
+            shared.line_ids,
+            ["circle", "metropolitan", "hammersmith-city"]
+        );
+        assert!(merged
+            .iter()
+            .filter(|p| p.line_ids.len() == 1)
+            .all(|p| p.line_ids[0] == "circle"));
+    }
+
+    #[test]
+    fn test_freshness_policy() {
+        let now: DateTime<Utc> = "2024-01-01T12:00:00Z".parse().unwrap();
+        let prediction = |vehicle_id: &str, read: &str, time_to_live: &str, tts: i32| {
+            serde_json::from_value::<Prediction>(json!({
+                "vehicleId": vehicle_id,
+                "naptanId": "940GZZLUVIC",
+                "timeToStation": tts,
+                "timeToLive": time_to_live,
+                "timing": { "read": read },
+            }))
+            .unwrap()
+        };
+        let poll = |tts: i32| {
+            vec![
+                prediction("1", "2024-01-01T11:59:50Z", "2024-01-01T12:05:00Z", 300),
+                prediction("2", "2024-01-01T11:59:50Z", "2024-01-01T11:59:00Z", 300),
+                prediction("3", "2024-01-01T11:50:00Z", "2024-01-01T12:05:00Z", 300),
+                prediction("4", "2024-01-01T11:59:50Z", "2024-01-01T12:05:00Z", tts),
+            ]
+        };
+
+        let freshness = Freshness::new(FreshnessPolicy::default());
+        let (flagged, report) = freshness.apply(poll(300), now);
+        let reasons: Vec<Option<StaleReason>> = flagged.iter().map(|p| p.stale).collect();
+        assert_eq!(
+            reasons,
+            [
+                None,
+                Some(StaleReason::Expired),
+                Some(StaleReason::OldRead),
+                None
+            ]
+        );
+        assert_eq!((report.dropped, report.flagged), (0, 2));
+
+        // Vehicle 1 hasn't moved in four minutes; vehicle 4 has
+        let later = now + Duration::minutes(4);
+        let freshness = Freshness::new(FreshnessPolicy {
+            action: StaleAction::Drop,
+            max_read_age: 3600,
+            ..Default::default()
+        });
+        freshness.apply(poll(300), now);
+        let (kept, report) = freshness.apply(poll(60), later);
+        let kept: Vec<&str> = kept
+            .iter()
+            .filter_map(|p| p.vehicle_id.as_deref())
+            .collect();
+        assert_eq!(kept, ["4"]);
+        assert_eq!(report.dropped, 3);
+    }
+}
@@ -0,0 +1,140 @@
+use serde_json::{Map, Value};
+
+// Field projection for results: `fields=` keeps only the listed paths, `exclude=`
+// drops paths. Paths are dotted serialized field names (`timing.read`), and apply
+// to every element when they pass through an array (`affectedRoutes.lineId`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Projection {
+    fields: Vec<Vec<String>>,
+    exclude: Vec<Vec<String>>,
+}
+
+fn parse_paths(list: Option<&str>) -> Vec<Vec<String>> {
+    list.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(|path| path.split('.').map(str::to_string).collect())
+        .collect()
+}
+
+impl Projection {
+    pub fn new(fields: Option<&str>, exclude: Option<&str>) -> Self {
+        Self {
+            fields: parse_paths(fields),
+            exclude: parse_paths(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn apply(&self, mut value: Value) -> Value {
+        if !self.fields.is_empty() {
+            let paths: Vec<&[String]> = self.fields.iter().map(Vec::as_slice).collect();
+            value = select(value, &paths);
+        }
+        for path in &self.exclude {
+            remove(&mut value, path);
+        }
+        value
+    }
+
+    // Whether a top-level field survives, for formats that can only project whole
+    // columns. A nested path keeps its whole top-level field.
+    #[cfg_attr(not(feature = "arrow"), allow(dead_code))]
+    pub fn keeps(&self, field: &str) -> bool {
+        let selected = self.fields.is_empty() || self.fields.iter().any(|path| path[0] == field);
+        let excluded = self
+            .exclude
+            .iter()
+            .any(|path| path.len() == 1 && path[0] == field);
+        selected && !excluded
+    }
+}
+
+fn select(value: Value, paths: &[&[String]]) -> Value {
+    // A path ending here selects the whole value
+    if paths.iter().any(|path| path.is_empty()) {
+        return value;
+    }
+
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter_map(|(key, value)| {
+                    let rest: Vec<&[String]> = paths
+                        .iter()
+                        .filter(|path| path[0] == key)
+                        .map(|path| &path[1..])
+                        .collect();
+                    (!rest.is_empty()).then(|| (key, select(value, &rest)))
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(items) => {
+            Value::Array(items.into_iter().map(|item| select(item, paths)).collect())
+        }
+        other => other,
+    }
+}
+
+fn remove(value: &mut Value, path: &[String]) {
+    match value {
+        Value::Object(map) => match path {
+            [key] => {
+                map.shift_remove(key);
+            }
+            [key, rest @ ..] => {
+                if let Some(child) = map.get_mut(key) {
+                    remove(child, rest);
+                }
+            }
+            [] => {}
+        },
+        Value::Array(items) => items.iter_mut().for_each(|item| remove(item, path)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_projection() {
+        let prediction = json!({
+            "lineId": "victoria",
+            "platformName": "Northbound - Platform 3",
+            "towards": "Walthamstow Central",
+            "timing": { "read": "2024-01-01T12:00:00Z", "sent": "2024-01-01T12:00:01Z" },
+        });
+
+        let projection = Projection::new(Some("timing.read, lineId"), None);
+        assert_eq!(
+            projection.apply(prediction.clone()),
+            json!({ "lineId": "victoria", "timing": { "read": "2024-01-01T12:00:00Z" } })
+        );
+
+        let projection = Projection::new(None, Some("towards,timing.sent"));
+        assert_eq!(
+            projection.apply(prediction),
+            json!({
+                "lineId": "victoria",
+                "platformName": "Northbound - Platform 3",
+                "timing": { "read": "2024-01-01T12:00:00Z" },
+            })
+        );
+
+        let disruption = json!({ "affectedRoutes": [{ "lineId": "victoria", "name": "x" }] });
+        let projection = Projection::new(Some("affectedRoutes.lineId"), None);
+        assert_eq!(
+            projection.apply(disruption),
+            json!({ "affectedRoutes": [{ "lineId": "victoria" }] })
+        );
+        assert!(projection.keeps("affectedRoutes"));
+        assert!(!projection.keeps("summary"));
+    }
+}
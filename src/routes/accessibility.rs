@@ -0,0 +1,74 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+use crate::error::AppResult;
+use crate::format::{Formatted, ResponseOptions};
+use crate::models::{LiftDisruption, StationAccessibility};
+use crate::routes::create_response;
+use crate::tfl::TflClient;
+
+pub fn accessibility_routes() -> Router {
+    let tfl_client = Arc::new(TflClient::new());
+
+    Router::new()
+        .route(
+            "/stations/:id/accessibility",
+            get(get_station_accessibility),
+        )
+        .route("/lift-disruptions", get(get_lift_disruptions))
+        .with_state(tfl_client)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LiftDisruptionQuery {
+    station: Option<String>,
+}
+
+// Handler for /stations/:id/accessibility
+async fn get_station_accessibility(
+    options: ResponseOptions,
+    State(tfl_client): State<Arc<TflClient>>,
+    Path(station_id): Path<String>,
+) -> AppResult<Formatted<StationAccessibility>> {
+    let start_time = Instant::now();
+
+    info!("Received station_id={}", station_id);
+
+    let (stop_point, lift_disruptions) = tokio::try_join!(
+        tfl_client.get_stop_point(&station_id),
+        tfl_client.get_lift_disruptions()
+    )?;
+
+    let accessibility = StationAccessibility::from_stop_point(&stop_point, &lift_disruptions);
+
+    let response = create_response(start_time, &station_id, vec![accessibility]);
+    Ok(options.respond(response))
+}
+
+// Handler for /lift-disruptions
+// Optionally restricted to one station by its NaPTAN id
+async fn get_lift_disruptions(
+    options: ResponseOptions,
+    State(tfl_client): State<Arc<TflClient>>,
+    Query(params): Query<LiftDisruptionQuery>,
+) -> AppResult<Formatted<LiftDisruption>> {
+    let start_time = Instant::now();
+    let query = params.station.unwrap_or_default();
+
+    info!("Received station={}", query);
+
+    let mut disruptions = tfl_client.get_lift_disruptions().await?;
+    if !query.is_empty() {
+        disruptions.retain(|d| d.naptan_code.as_deref() == Some(query.as_str()));
+    }
+
+    let response = create_response(start_time, &query, disruptions);
+    Ok(options.respond(response))
+}
@@ -0,0 +1,59 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+use crate::accuracy::PredictionHistory;
+use crate::error::AppResult;
+use crate::format::{Formatted, ResponseOptions};
+use crate::models::PredictionAccuracy;
+use crate::routes::create_response;
+
+#[derive(Clone)]
+pub struct AnalyticsState {
+    history: Arc<PredictionHistory>,
+}
+
+pub fn analytics_routes(history: Arc<PredictionHistory>) -> Router {
+    let state = AnalyticsState { history };
+
+    Router::new()
+        .route(
+            "/analytics/prediction-accuracy",
+            get(get_prediction_accuracy),
+        )
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccuracyQuery {
+    lines: Option<String>,
+}
+
+// Handler for /analytics/prediction-accuracy
+async fn get_prediction_accuracy(
+    options: ResponseOptions,
+    State(state): State<AnalyticsState>,
+    Query(params): Query<AccuracyQuery>,
+) -> AppResult<Formatted<PredictionAccuracy>> {
+    let start_time = Instant::now();
+    let lines = params.lines.unwrap_or_default();
+
+    info!("Received lines={}", lines);
+
+    let line_ids: Vec<&str> = lines
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect();
+    let accuracy = state
+        .history
+        .accuracy((!line_ids.is_empty()).then_some(line_ids.as_slice()));
+
+    Ok(options.respond(create_response(start_time, &lines, accuracy)))
+}
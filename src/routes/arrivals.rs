@@ -1,25 +1,46 @@
 use axum::{
-    extract::{Query, State},
+    extract::{Path, Query, State},
     routing::get,
-    Json, Router,
+    Router,
 };
+use chrono::Utc;
+use futures::future::try_join_all;
+use futures::{stream, StreamExt};
 use serde::Deserialize;
 use std::sync::Arc;
 use std::time::Instant;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::error::AppResult;
-use crate::models::{Prediction, Response};
+use crate::board;
+use crate::dataset::Dataset;
+use crate::error::{AppError, AppResult};
+use crate::format::{Formatted, ResponseOptions};
+use crate::models::{ModeArrivals, PlatformArrivals, PlatformBoard, Prediction, StopPoint};
+use crate::positions::locate_all;
+use crate::predictions::{merge_shared, Freshness};
 use crate::routes::create_response;
 use crate::tfl::TflClient;
 
-pub fn arrivals_routes() -> Router {
-    let tfl_client = Arc::new(TflClient::new());
+#[derive(Clone)]
+pub struct ArrivalsState {
+    tfl_client: Arc<TflClient>,
+    dataset: Arc<Dataset>,
+    freshness: Arc<Freshness>,
+}
+
+pub fn arrivals_routes(dataset: Arc<Dataset>, freshness: Arc<Freshness>) -> Router {
+    let state = ArrivalsState {
+        tfl_client: Arc::new(TflClient::new()),
+        dataset,
+        freshness,
+    };
 
     Router::new()
         .route("/arrivals-by-lines", get(get_arrivals_by_lines))
         .route("/arrivals-by-station", get(get_arrivals_by_station))
-        .with_state(tfl_client)
+        .route("/arrivals-by-hub/:hub_id", get(get_arrivals_by_hub))
+        .route("/departure-board/:station_id", get(get_departure_board))
+        .with_state(state)
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,33 +51,58 @@ pub struct ArrivalsQuery {
 
 // Handler for /arrivals-by-lines
 async fn get_arrivals_by_lines(
-    State(tfl_client): State<Arc<TflClient>>,
+    options: ResponseOptions,
+    State(state): State<ArrivalsState>,
     Query(params): Query<ArrivalsQuery>,
-) -> AppResult<Json<Response<Prediction>>> {
+) -> AppResult<Formatted<Prediction>> {
     let start_time = Instant::now();
     let query = params.query;
 
     info!("Received query={}", query);
 
     // In the Python version, this parses comma-separated line IDs
-    // We'll handle a single line ID for simplicity
-    let lines: Vec<&str> = query.split(',').collect();
-    let mut all_arrivals = Vec::new();
+    let fetches: Vec<_> = query
+        .split(',')
+        .map(|line| {
+            let state = state.clone();
+            let line = line.trim().to_string();
+            async move {
+                let mut predictions = state.tfl_client.get_arrivals_by_line(&line).await?;
+                locate_all(&state.dataset, &mut predictions);
+                Ok::<_, AppError>(predictions)
+            }
+        })
+        .collect();
 
-    for line in lines {
-        let arrivals = tfl_client.get_arrivals_by_line(line.trim()).await?;
-        all_arrivals.extend(arrivals);
+    // A stream has sent its metadata before any line's predictions arrive, so the
+    // policy is applied line by line and its report left out
+    if options.streams() {
+        let fetches = fetches
+            .into_iter()
+            .map(|fetch| {
+                let freshness = state.freshness.clone();
+                async move {
+                    let predictions = fetch.await?;
+                    Ok(freshness.apply(predictions, Utc::now()).0)
+                }
+            })
+            .collect();
+        return options.respond_fan_out(start_time, &query, fetches).await;
     }
 
-    let response = create_response(start_time, &query, all_arrivals);
-    Ok(Json(response))
+    let predictions = try_join_all(fetches).await?.into_iter().flatten().collect();
+    let (predictions, report) = state.freshness.apply(predictions, Utc::now());
+    let mut response = create_response(start_time, &query, predictions);
+    response.context.freshness = Some(report);
+    Ok(options.respond(response))
 }
 
 // Handler for /arrivals-by-station
 async fn get_arrivals_by_station(
-    State(tfl_client): State<Arc<TflClient>>,
+    options: ResponseOptions,
+    State(state): State<ArrivalsState>,
     Query(params): Query<ArrivalsQuery>,
-) -> AppResult<Json<Response<Prediction>>> {
+) -> AppResult<Formatted<Prediction>> {
     let start_time = Instant::now();
     let query = params.query;
     let lines = params.lines.unwrap_or_else(|| "tube".to_string());
@@ -67,17 +113,248 @@ async fn get_arrivals_by_station(
     let station_id = query.clone();
 
     // In the Python version, this handles multiple line IDs
-    // For simplicity, we'll use the first line in the list
-    let line_ids: Vec<&str> = lines.split(',').collect();
+    let fetches: Vec<_> = lines
+        .split(',')
+        .map(|line_id| {
+            let state = state.clone();
+            let line_id = line_id.trim().to_string();
+            let station_id = station_id.clone();
+            async move {
+                let mut predictions = state
+                    .tfl_client
+                    .get_arrivals_by_line_at_stop(&line_id, &station_id)
+                    .await?;
+                locate_all(&state.dataset, &mut predictions);
+                Ok::<_, AppError>(predictions)
+            }
+        })
+        .collect();
+
+    // A stream writes each line's predictions as they arrive, so a train on track
+    // shared by several of the lines is only merged with itself within a line
+    if options.streams() {
+        let fetches = fetches
+            .into_iter()
+            .map(|fetch| {
+                let freshness = state.freshness.clone();
+                async move {
+                    let predictions = fetch.await?;
+                    Ok(merge_shared(freshness.apply(predictions, Utc::now()).0))
+                }
+            })
+            .collect();
+        return options
+            .respond_fan_out(start_time, &station_id, fetches)
+            .await;
+    }
+
+    // Otherwise a train on shared track is listed once
+    let predictions = try_join_all(fetches).await?.into_iter().flatten().collect();
+    let (predictions, report) = state.freshness.apply(predictions, Utc::now());
+
+    let mut response = create_response(start_time, &station_id, merge_shared(predictions));
+    response.context.freshness = Some(report);
+    Ok(options.respond(response))
+}
+
+// Child stops fetched at once for a hub
+const HUB_CONCURRENCY: usize = 8;
+
+// Stop types that TfL serves arrivals for directly. Anything else (hubs, bus stop
+// clusters) is walked down to its children.
+const ARRIVAL_STOP_TYPES: &[&str] = &[
+    "NaptanMetroStation",
+    "NaptanRailStation",
+    "NaptanPublicBusCoachTram",
+    "NaptanFerryPort",
+];
+
+fn collect_arrival_stops(stop_point: &StopPoint, stop_ids: &mut Vec<String>) {
+    let is_arrival_stop = stop_point
+        .stop_type
+        .as_deref()
+        .is_some_and(|t| ARRIVAL_STOP_TYPES.contains(&t));
+
+    match (is_arrival_stop, &stop_point.naptan_id) {
+        (true, Some(naptan_id)) => {
+            if !stop_ids.contains(naptan_id) {
+                stop_ids.push(naptan_id.clone());
+            }
+        }
+        _ => {
+            for child in &stop_point.children {
+                collect_arrival_stops(child, stop_ids);
+            }
+        }
+    }
+}
+
+// Group predictions by mode, then platform. Predictions are sorted by time to
+// station first, so groups come out in order of their next arrival.
+fn group_by_mode_and_platform(mut predictions: Vec<Prediction>) -> Vec<ModeArrivals> {
+    predictions.sort_by_key(|p| p.time_to_station.unwrap_or(i32::MAX));
+
+    let mut modes: Vec<ModeArrivals> = Vec::new();
+    for prediction in predictions {
+        let mode_name = prediction.mode_name.clone().unwrap_or_default();
+        let platform_name = prediction.platform_name.clone().unwrap_or_default();
+
+        let mode_index = match modes.iter().position(|m| m.mode_name == mode_name) {
+            Some(i) => i,
+            None => {
+                modes.push(ModeArrivals {
+                    mode_name,
+                    platforms: Vec::new(),
+                });
+                modes.len() - 1
+            }
+        };
+        let platforms = &mut modes[mode_index].platforms;
+
+        match platforms
+            .iter_mut()
+            .find(|p| p.platform_name == platform_name)
+        {
+            Some(platform) => platform.arrivals.push(prediction),
+            None => platforms.push(PlatformArrivals {
+                platform_name,
+                arrivals: vec![prediction],
+            }),
+        }
+    }
+    modes
+}
+
+// Handler for /arrivals-by-hub/:hub_id
+// Resolves every child stop of the hub and fetches their arrivals concurrently
+async fn get_arrivals_by_hub(
+    options: ResponseOptions,
+    State(state): State<ArrivalsState>,
+    Path(hub_id): Path<String>,
+) -> AppResult<Formatted<ModeArrivals>> {
+    let start_time = Instant::now();
+
+    info!("Received hub_id={}", hub_id);
+
+    let hub = state.tfl_client.get_stop_point(&hub_id).await?;
+    let mut stop_ids = Vec::new();
+    collect_arrival_stops(&hub, &mut stop_ids);
+
+    let results: Vec<_> = stream::iter(stop_ids)
+        .map(|stop_id| {
+            let tfl_client = state.tfl_client.clone();
+            async move {
+                let result = tfl_client.get_arrivals_at_stop(&stop_id).await;
+                (stop_id, result)
+            }
+        })
+        .buffer_unordered(HUB_CONCURRENCY)
+        .collect()
+        .await;
+
+    // One unavailable stop shouldn't blank the whole board
     let mut all_arrivals = Vec::new();
+    let mut first_error = None;
+    for (stop_id, result) in results {
+        match result {
+            Ok(arrivals) => all_arrivals.extend(arrivals),
+            Err(e) => {
+                warn!("Failed to fetch arrivals at {}: {}", stop_id, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    locate_all(&state.dataset, &mut all_arrivals);
+    if all_arrivals.is_empty() {
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+    }
+    let (all_arrivals, report) = state.freshness.apply(all_arrivals, Utc::now());
+
+    let mut response = create_response(
+        start_time,
+        &hub_id,
+        group_by_mode_and_platform(all_arrivals),
+    );
+    response.context.freshness = Some(report);
+    Ok(options.respond(response))
+}
+
+// Departures shown per platform unless `count=` says otherwise
+const DEFAULT_BOARD_COUNT: usize = 3;
+
+#[derive(Debug, Deserialize)]
+pub struct BoardQuery {
+    lines: Option<String>,
+    count: Option<usize>,
+}
 
-    for line_id in line_ids {
-        let arrivals = tfl_client
-            .get_arrivals_by_line_at_stop(line_id.trim(), &station_id)
-            .await?;
-        all_arrivals.extend(arrivals);
+// Handler for /departure-board/:station_id
+// The next departures from each platform, for all lines at the station unless
+// `lines=` narrows them down
+async fn get_departure_board(
+    options: ResponseOptions,
+    State(state): State<ArrivalsState>,
+    Path(station_id): Path<String>,
+    Query(params): Query<BoardQuery>,
+) -> AppResult<Formatted<PlatformBoard>> {
+    let start_time = Instant::now();
+    let count = params.count.unwrap_or(DEFAULT_BOARD_COUNT);
+
+    info!(
+        "Received station_id={}, lines={:?}",
+        station_id, params.lines
+    );
+
+    let mut predictions = state.tfl_client.get_arrivals_at_stop(&station_id).await?;
+    if let Some(lines) = &params.lines {
+        let line_ids: Vec<&str> = lines.split(',').map(str::trim).collect();
+        predictions.retain(|p| {
+            p.line_id
+                .as_deref()
+                .is_some_and(|id| line_ids.contains(&id))
+        });
     }
 
-    let response = create_response(start_time, &station_id, all_arrivals);
-    Ok(Json(response))
+    let (predictions, report) = state.freshness.apply(predictions, Utc::now());
+
+    let predictions = merge_shared(predictions);
+    let mut response = create_response(start_time, &station_id, board::build(predictions, count));
+    response.context.freshness = Some(report);
+    Ok(options.respond(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prediction(mode: &str, platform: &str, time_to_station: i32) -> Prediction {
+        serde_json::from_value(serde_json::json!({
+            "modeName": mode,
+            "platformName": platform,
+            "timeToStation": time_to_station,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_group_by_mode_and_platform() {
+        let modes = group_by_mode_and_platform(vec![
+            prediction("tube", "Eastbound - Platform 3", 240),
+            prediction("dlr", "Platform 4a", 60),
+            prediction("tube", "Westbound - Platform 6", 120),
+            prediction("tube", "Eastbound - Platform 3", 30),
+        ]);
+
+        assert_eq!(modes.len(), 2);
+        assert_eq!(modes[0].mode_name, "tube");
+        assert_eq!(
+            modes[0].platforms[0].platform_name,
+            "Eastbound - Platform 3"
+        );
+        assert_eq!(modes[0].platforms[0].arrivals.len(), 2);
+        assert_eq!(modes[0].platforms[0].arrivals[1].time_to_station, Some(240));
+        assert_eq!(modes[1].mode_name, "dlr");
+    }
 }
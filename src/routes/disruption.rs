@@ -1,24 +1,49 @@
 use axum::{
     extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
     routing::get,
-    Json, Router,
+    Router,
 };
+use futures::stream::{self, Stream};
 use serde::Deserialize;
+use std::collections::VecDeque;
+use std::convert::Infallible;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
 use tracing::info;
 
-use crate::error::AppResult;
-use crate::models::{Disruption, Response};
+use crate::changes::{DisruptionFeed, Resume};
+use crate::error::{AppError, AppResult};
+use crate::format::{Formatted, ResponseOptions};
+use crate::models::{Disruption, DisruptionChange};
 use crate::routes::create_response;
 use crate::tfl::TflClient;
 
-pub fn disruption_routes() -> Router {
-    let tfl_client = Arc::new(TflClient::new());
+pub const ALLOWED_MODES: [&str; 4] = ["tube", "overground", "dlr", "elizabeth-line"];
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Clone)]
+pub struct DisruptionState {
+    tfl_client: Arc<TflClient>,
+    feed: Arc<DisruptionFeed>,
+}
+
+pub fn disruption_routes(feed: Arc<DisruptionFeed>) -> Router {
+    let state = DisruptionState {
+        tfl_client: Arc::new(TflClient::new()),
+        feed,
+    };
 
     Router::new()
         .route("/disruption-by-modes", get(get_disruption_by_modes))
-        .with_state(tfl_client)
+        .route("/disruptions/changes", get(get_disruption_changes))
+        .route(
+            "/disruptions/changes/stream",
+            get(stream_disruption_changes),
+        )
+        .with_state(state)
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,22 +53,36 @@ pub struct DisruptionQuery {
 
 // Handler for /disruption-by-modes
 async fn get_disruption_by_modes(
-    State(tfl_client): State<Arc<TflClient>>,
+    options: ResponseOptions,
+    State(state): State<DisruptionState>,
     Query(params): Query<DisruptionQuery>,
-) -> AppResult<Json<Response<Disruption>>> {
+) -> AppResult<Formatted<Disruption>> {
     let start_time = Instant::now();
     let query = params.query;
 
     info!("Received query={}", query);
 
-    // Process comma-separated modes
-    let modes: Vec<&str> = query.split(',').collect();
-    let mut all_disruptions = Vec::new();
+    let modes = parse_modes(&query)?;
+
+    // Fetch disruptions for each mode
+    let fetches = modes
+        .into_iter()
+        .map(|mode| {
+            let tfl_client = state.tfl_client.clone();
+            async move { tfl_client.get_disruptions_by_mode(&mode).await }
+        })
+        .collect();
+
+    options.respond_fan_out(start_time, &query, fetches).await
+}
+
+// Process and validate comma-separated modes
+pub fn parse_modes(query: &str) -> AppResult<Vec<String>> {
+    let modes: Vec<String> = query.split(',').map(|m| m.trim().to_string()).collect();
 
     // Validate that all modes are allowed
-    let allowed_modes = ["tube", "overground", "dlr", "elizabeth-line"];
     for mode in &modes {
-        if !allowed_modes.contains(mode) {
+        if !ALLOWED_MODES.contains(&mode.as_str()) {
             return Err(crate::error::AppError::ParseError(format!(
                 "Invalid mode: {}",
                 mode
@@ -51,12 +90,136 @@ async fn get_disruption_by_modes(
         }
     }
 
-    // Fetch disruptions for each mode
-    for mode in modes {
-        let disruptions = tfl_client.get_disruptions_by_mode(mode.trim()).await?;
-        all_disruptions.extend(disruptions);
-    }
+    Ok(modes)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    since: Option<String>,
+    modes: Option<String>,
+}
+
+// Where to resume the feed from: a change id, sequence number or RFC 3339 time
+fn parse_since(since: Option<&str>) -> AppResult<Resume> {
+    let Some(since) = since else {
+        return Ok(Resume::Start);
+    };
+    Resume::parse(since).ok_or_else(|| {
+        AppError::ParseError(format!(
+            "Invalid since: {} (expected a change id, sequence number or RFC 3339 time)",
+            since
+        ))
+    })
+}
+
+fn mode_filter(modes: Option<&str>) -> AppResult<Option<Vec<String>>> {
+    modes.map(parse_modes).transpose()
+}
+
+fn in_modes(modes: &Option<Vec<String>>, change: &DisruptionChange) -> bool {
+    modes
+        .as_ref()
+        .is_none_or(|modes| modes.contains(&change.mode))
+}
+
+// Handler for /disruptions/changes
+async fn get_disruption_changes(
+    options: ResponseOptions,
+    State(state): State<DisruptionState>,
+    Query(params): Query<ChangesQuery>,
+) -> AppResult<Formatted<DisruptionChange>> {
+    let start_time = Instant::now();
+    let since = parse_since(params.since.as_deref())?;
+    let modes = mode_filter(params.modes.as_deref())?;
+
+    let catchup = state.feed.resume(&since);
+    let changes = catchup
+        .changes
+        .into_iter()
+        .filter(|change| in_modes(&modes, change))
+        .collect();
+    let query = params.since.unwrap_or_default();
+
+    let mut response = create_response(start_time, &query, changes);
+    response.context.gap = Some(catchup.gap);
+    Ok(options.respond(response))
+}
+
+// Handler for /disruptions/changes/stream: kept changes since `since=` (or the
+// `Last-Event-ID` of a reconnecting client), then each change as it's detected.
+// A `gap` event comes first when some changes since then are no longer kept, and
+// whenever a slow client falls further behind than the kept changes reach.
+async fn stream_disruption_changes(
+    State(state): State<DisruptionState>,
+    headers: HeaderMap,
+    Query(params): Query<ChangesQuery>,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok());
+    let since = parse_since(last_event_id.or(params.since.as_deref()))?;
+    let modes = mode_filter(params.modes.as_deref())?;
+
+    let receiver = state.feed.subscribe();
+    let catchup = state.feed.resume(&since);
+    let gap = catchup.gap;
+    let pending: VecDeque<DisruptionChange> = catchup.changes.into();
+    // Everything the catch-up covered, or the resume point if it was this boot's
+    let last_sequence = match (pending.back(), &since) {
+        (Some(change), _) => change.sequence,
+        (None, Resume::Sequence { sequence, .. }) if !gap => *sequence,
+        (None, _) => 0,
+    };
+
+    let events = stream::unfold(
+        (state.feed, receiver, pending, last_sequence, gap),
+        move |(feed, mut receiver, mut pending, mut last_sequence, mut gap)| {
+            let modes = modes.clone();
+            async move {
+                loop {
+                    if gap {
+                        gap = false;
+                        let event = Event::default()
+                            .event("gap")
+                            .data("Some changes are no longer kept; refetch current disruptions");
+                        return Some((Ok(event), (feed, receiver, pending, last_sequence, gap)));
+                    }
+                    if let Some(change) = pending.pop_front() {
+                        last_sequence = last_sequence.max(change.sequence);
+                        if !in_modes(&modes, &change) {
+                            continue;
+                        }
+                        let event = Event::default()
+                            .event("change")
+                            .id(change.id.clone())
+                            .json_data(&change)
+                            .unwrap_or_else(|e| {
+                                Event::default().event("error").data(e.to_string())
+                            });
+                        return Some((Ok(event), (feed, receiver, pending, last_sequence, gap)));
+                    }
+                    match receiver.recv().await {
+                        Ok(change) if change.sequence > last_sequence => pending.push_back(change),
+                        Ok(_) => {}
+                        // Fell behind; catch up from the kept changes
+                        Err(RecvError::Lagged(_)) => {
+                            let catchup = feed.resume(&Resume::Sequence {
+                                epoch: None,
+                                sequence: last_sequence,
+                            });
+                            gap = catchup.gap;
+                            pending.extend(catchup.changes);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        },
+    );
 
-    let response = create_response(start_time, &query, all_disruptions);
-    Ok(Json(response))
+    Ok(Sse::new(events).keep_alive(
+        KeepAlive::new()
+            .interval(HEARTBEAT_INTERVAL)
+            .text("heartbeat"),
+    ))
 }
@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use futures::future::try_join_all;
+use prost::Message;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::dataset::{Dataset, TUBE_LINES};
+use crate::error::AppResult;
+use crate::gtfs;
+use crate::gtfs_rt::{self, FeedMessage};
+use crate::routes::disruption::parse_modes;
+use crate::tfl::TflClient;
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+#[derive(Clone)]
+pub struct GtfsState {
+    tfl_client: Arc<TflClient>,
+    dataset: Arc<Dataset>,
+    feeds: Arc<gtfs::FeedCache>,
+}
+
+pub fn gtfs_routes(dataset: Arc<Dataset>) -> Router {
+    let state = GtfsState {
+        tfl_client: Arc::new(TflClient::new()),
+        dataset,
+        feeds: Arc::new(gtfs::FeedCache::default()),
+    };
+
+    Router::new()
+        .route("/gtfs.zip", get(get_gtfs_feed))
+        .route("/gtfs-rt/trip-updates", get(get_trip_updates))
+        .route("/gtfs-rt/alerts", get(get_alerts))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GtfsQuery {
+    lines: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertsQuery {
+    modes: Option<String>,
+}
+
+fn parse_lines(lines: Option<&str>) -> Vec<String> {
+    lines
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn protobuf(feed: FeedMessage) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)],
+        feed.encode_to_vec(),
+    )
+}
+
+// Handler for /gtfs.zip
+async fn get_gtfs_feed(
+    State(state): State<GtfsState>,
+    Query(query): Query<GtfsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let line_ids = parse_lines(query.lines.as_deref());
+    info!("Building GTFS feed for lines: {:?}", line_ids);
+
+    let feed = state
+        .feeds
+        .get(&state.tfl_client, &state.dataset, &line_ids)
+        .await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"gtfs.zip\"",
+            ),
+        ],
+        feed,
+    ))
+}
+
+// Handler for /gtfs-rt/trip-updates
+async fn get_trip_updates(
+    State(state): State<GtfsState>,
+    Query(query): Query<GtfsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let mut line_ids = parse_lines(query.lines.as_deref());
+    if line_ids.is_empty() {
+        line_ids = TUBE_LINES.iter().map(|line| line.to_string()).collect();
+    }
+    info!("Building GTFS-RT trip updates for lines: {:?}", line_ids);
+
+    let predictions: Vec<_> = try_join_all(
+        line_ids
+            .iter()
+            .map(|line_id| state.tfl_client.get_arrivals_by_line(line_id)),
+    )
+    .await?
+    .into_iter()
+    .flatten()
+    .collect();
+
+    Ok(protobuf(gtfs_rt::trip_updates(&predictions, Utc::now())))
+}
+
+// Handler for /gtfs-rt/alerts
+async fn get_alerts(
+    State(state): State<GtfsState>,
+    Query(query): Query<AlertsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let modes = parse_modes(query.modes.as_deref().unwrap_or("tube"))?;
+    info!("Building GTFS-RT alerts for modes: {:?}", modes);
+
+    let disruptions: Vec<_> = try_join_all(
+        modes
+            .iter()
+            .map(|mode| state.tfl_client.get_disruptions_by_mode(mode)),
+    )
+    .await?
+    .into_iter()
+    .flatten()
+    .collect();
+
+    Ok(protobuf(gtfs_rt::alerts(&disruptions, Utc::now())))
+}
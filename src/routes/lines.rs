@@ -0,0 +1,169 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+use crate::dataset::Dataset;
+use crate::error::AppResult;
+use crate::format::{Formatted, ResponseOptions};
+use crate::headways::{self, DEFAULT_GAP_THRESHOLD};
+use crate::models::{Prediction, StationHeadways, TrainLocation};
+use crate::positions::{coordinates, locate};
+use crate::predictions::Freshness;
+use crate::routes::create_response;
+use crate::tfl::TflClient;
+
+#[derive(Clone)]
+pub struct LinesState {
+    tfl_client: Arc<TflClient>,
+    dataset: Arc<Dataset>,
+    freshness: Arc<Freshness>,
+}
+
+pub fn lines_routes(dataset: Arc<Dataset>, freshness: Arc<Freshness>) -> Router {
+    let state = LinesState {
+        tfl_client: Arc::new(TflClient::new()),
+        dataset,
+        freshness,
+    };
+
+    Router::new()
+        .route("/lines/:id/positions", get(get_line_positions))
+        .route("/lines/:id/headways", get(get_line_headways))
+        .with_state(state)
+}
+
+// Each train's location, from the prediction for its next stop (the one with the
+// least time to station, any without a time coming last)
+fn train_locations(dataset: &Dataset, predictions: &[Prediction]) -> Vec<TrainLocation> {
+    let mut next_stops: BTreeMap<&str, &Prediction> = BTreeMap::new();
+    for prediction in predictions {
+        let Some(vehicle_id) = prediction.vehicle_id.as_deref() else {
+            continue;
+        };
+        let next = next_stops.entry(vehicle_id).or_insert(prediction);
+        if prediction.time_to_station.unwrap_or(i32::MAX) < next.time_to_station.unwrap_or(i32::MAX)
+        {
+            *next = prediction;
+        }
+    }
+
+    next_stops
+        .into_iter()
+        .map(|(vehicle_id, prediction)| {
+            let position = locate(dataset, prediction);
+            let point = position
+                .as_ref()
+                .and_then(|position| coordinates(dataset, position));
+            TrainLocation {
+                vehicle_id: vehicle_id.to_string(),
+                line_id: prediction.line_id.clone(),
+                direction: prediction.direction.clone(),
+                destination_name: prediction.destination_name.clone(),
+                current_location: prediction.current_location.clone(),
+                position,
+                next_stop_naptan_id: prediction.naptan_id.clone(),
+                next_stop_name: prediction.station_name.clone(),
+                time_to_next_stop: prediction.time_to_station,
+                lat: point.map(|(lat, _)| lat),
+                lon: point.map(|(_, lon)| lon),
+            }
+        })
+        .collect()
+}
+
+// Handler for /lines/:id/positions
+async fn get_line_positions(
+    options: ResponseOptions,
+    State(state): State<LinesState>,
+    Path(line_id): Path<String>,
+) -> AppResult<Formatted<TrainLocation>> {
+    let start_time = Instant::now();
+
+    info!("Received line_id={}", line_id);
+
+    let predictions = state.tfl_client.get_arrivals_by_line(&line_id).await?;
+    let (predictions, report) = state.freshness.apply(predictions, Utc::now());
+    let locations = train_locations(&state.dataset, &predictions);
+
+    let mut response = create_response(start_time, &line_id, locations);
+    response.context.freshness = Some(report);
+    Ok(options.respond(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeadwaysQuery {
+    threshold: Option<i64>,
+}
+
+// Handler for /lines/:id/headways
+async fn get_line_headways(
+    options: ResponseOptions,
+    State(state): State<LinesState>,
+    Path(line_id): Path<String>,
+    Query(params): Query<HeadwaysQuery>,
+) -> AppResult<Formatted<StationHeadways>> {
+    let start_time = Instant::now();
+    let threshold = params.threshold.unwrap_or(DEFAULT_GAP_THRESHOLD);
+
+    info!("Received line_id={}, threshold={}", line_id, threshold);
+
+    let predictions = state.tfl_client.get_arrivals_by_line(&line_id).await?;
+    let (predictions, report) = state.freshness.apply(predictions, Utc::now());
+
+    let mut response = create_response(
+        start_time,
+        &line_id,
+        headways::compute(predictions, threshold),
+    );
+    response.context.freshness = Some(report);
+    Ok(options.respond(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_train_locations() {
+        let dataset = Dataset::load();
+        let station = &dataset.stations[0];
+        let prediction =
+            |vehicle_id: &str, time_to_station: Option<i32>, location: &str| -> Prediction {
+                let mut value = json!({
+                    "vehicleId": vehicle_id,
+                    "naptanId": station.station_unique_id,
+                    "currentLocation": location,
+                });
+                if let Some(time_to_station) = time_to_station {
+                    value["timeToStation"] = json!(time_to_station);
+                }
+                serde_json::from_value(value).unwrap()
+            };
+
+        let locations = train_locations(
+            &dataset,
+            &[
+                prediction("201", Some(300), "Somewhere else"),
+                prediction("201", Some(60), &format!("At {}", station.station_name)),
+                prediction("305", None, "Between depots"),
+                prediction("305", Some(120), "In sidings"),
+            ],
+        );
+
+        assert_eq!(locations.len(), 2);
+        assert_eq!(locations[0].vehicle_id, "201");
+        assert_eq!(locations[0].time_to_next_stop, Some(60));
+        assert_eq!(locations[0].lat, station.lat);
+        assert_eq!(locations[1].time_to_next_stop, Some(120));
+        assert_eq!(locations[1].lat, None);
+    }
+}
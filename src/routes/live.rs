@@ -0,0 +1,439 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response as AxumResponse,
+    },
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use futures::{
+    future::try_join_all,
+    stream::{self, Stream},
+    SinkExt, StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+use crate::dataset::Dataset;
+use crate::live::{Pollers, Subscription};
+use crate::models::{Disruption, Line, Prediction};
+use crate::positions::locate_all;
+use crate::predictions::{merge_shared, Freshness};
+use crate::routes::create_response;
+use crate::routes::disruption::parse_modes;
+use crate::tfl::TflClient;
+
+// TfL refreshes predictions about every 30 seconds; poll more often than that so
+// changes are pushed soon after they appear
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+// How long clients should wait before reconnecting a dropped stream
+const RETRY_INTERVAL: Duration = Duration::from_secs(3);
+// Messages queued for a WebSocket client before senders wait for it to catch up
+const OUTGOING_BUFFER: usize = 32;
+const MAX_TOPICS: usize = 50;
+
+// One set of pollers per kind of topic, shared by the SSE and WebSocket endpoints
+#[derive(Clone)]
+pub struct LiveState {
+    tfl_client: Arc<TflClient>,
+    dataset: Arc<Dataset>,
+    freshness: Arc<Freshness>,
+    arrivals: Arc<Pollers<Prediction>>,
+    statuses: Arc<Pollers<Line>>,
+    disruptions: Arc<Pollers<Disruption>>,
+}
+
+pub fn live_routes(dataset: Arc<Dataset>, freshness: Arc<Freshness>) -> Router {
+    let state = LiveState {
+        tfl_client: Arc::new(TflClient::new()),
+        dataset,
+        freshness,
+        arrivals: Arc::new(Pollers::new(POLL_INTERVAL)),
+        statuses: Arc::new(Pollers::new(POLL_INTERVAL)),
+        disruptions: Arc::new(Pollers::new(POLL_INTERVAL)),
+    };
+
+    Router::new()
+        .route(
+            "/arrivals-by-station/stream",
+            get(stream_arrivals_by_station),
+        )
+        .route("/ws", get(websocket))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    query: String,
+    lines: Option<String>,
+}
+
+// Subscribe to a station's arrivals on the given lines, located and with the stale
+// prediction policy applied as for /arrivals-by-station, and trains shared between
+// the lines merged
+fn subscribe_arrivals(
+    state: &LiveState,
+    station_id: &str,
+    lines: &str,
+) -> Subscription<Prediction> {
+    let mut line_ids: Vec<String> = lines.split(',').map(|l| l.trim().to_string()).collect();
+    line_ids.sort();
+    line_ids.dedup();
+    let key = format!("{}|{}", station_id, line_ids.join(","));
+
+    let tfl_client = state.tfl_client.clone();
+    let dataset = state.dataset.clone();
+    let freshness = state.freshness.clone();
+    let station_id = station_id.to_string();
+    state.arrivals.subscribe(&key, move || {
+        let tfl_client = tfl_client.clone();
+        let dataset = dataset.clone();
+        let freshness = freshness.clone();
+        let station_id = station_id.clone();
+        let line_ids = line_ids.clone();
+        async move {
+            let mut predictions: Vec<Prediction> = try_join_all(
+                line_ids
+                    .iter()
+                    .map(|line_id| tfl_client.get_arrivals_by_line_at_stop(line_id, &station_id)),
+            )
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+            locate_all(&dataset, &mut predictions);
+            let (predictions, _) = freshness.apply(predictions, Utc::now());
+            Ok(merge_shared(predictions))
+        }
+    })
+}
+
+fn subscribe_status(state: &LiveState, line_id: &str) -> Subscription<Line> {
+    let tfl_client = state.tfl_client.clone();
+    let line_id = line_id.to_string();
+    state.statuses.subscribe(&line_id.clone(), move || {
+        let tfl_client = tfl_client.clone();
+        let line_id = line_id.clone();
+        async move { tfl_client.get_line_status(&line_id).await }
+    })
+}
+
+fn subscribe_disruptions(state: &LiveState, mode: &str) -> Subscription<Disruption> {
+    let tfl_client = state.tfl_client.clone();
+    let mode = mode.to_string();
+    state.disruptions.subscribe(&mode.clone(), move || {
+        let tfl_client = tfl_client.clone();
+        let mode = mode.clone();
+        async move { tfl_client.get_disruptions_by_mode(&mode).await }
+    })
+}
+
+// Handler for /arrivals-by-station/stream
+async fn stream_arrivals_by_station(
+    State(state): State<LiveState>,
+    headers: HeaderMap,
+    Query(params): Query<StreamQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let station_id = params.query;
+    let lines = params.lines.unwrap_or_else(|| "tube".to_string());
+    let subscription = subscribe_arrivals(&state, &station_id, &lines);
+
+    info!(
+        "Streaming arrivals for query={}, lines={} ({} active pollers)",
+        station_id,
+        lines,
+        state.arrivals.active()
+    );
+
+    // A reconnecting client that has already seen the latest snapshot waits for
+    // the next change rather than being sent it again
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    Sse::new(snapshot_events(subscription, station_id, last_event_id)).keep_alive(
+        KeepAlive::new()
+            .interval(HEARTBEAT_INTERVAL)
+            .text("heartbeat"),
+    )
+}
+
+// One event per new snapshot, each carrying the whole Response envelope and the
+// snapshot's id as its event id
+fn snapshot_events(
+    subscription: Subscription<Prediction>,
+    query: String,
+    last_event_id: Option<String>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(
+        (subscription, last_event_id),
+        move |(mut subscription, mut last_event_id)| {
+            let query = query.clone();
+            async move {
+                loop {
+                    let latest = subscription.receiver.borrow_and_update().clone();
+                    if let Some(snapshot) = latest.filter(|s| last_event_id.as_ref() != Some(&s.id))
+                    {
+                        let response =
+                            create_response(Instant::now(), &query, snapshot.results.clone());
+                        let event = Event::default()
+                            .event("arrivals")
+                            .id(snapshot.id.clone())
+                            .retry(RETRY_INTERVAL)
+                            .json_data(response)
+                            .unwrap_or_else(|e| {
+                                Event::default().event("error").data(e.to_string())
+                            });
+                        last_event_id = Some(snapshot.id.clone());
+                        return Some((Ok(event), (subscription, last_event_id)));
+                    }
+                    // The poller has stopped
+                    subscription.receiver.changed().await.ok()?;
+                }
+            }
+        },
+    )
+}
+
+// Topics a /ws client can subscribe to, named `arrivals:<station>[:<lines>]`,
+// `status:<line>` or `disruptions:<mode>`
+#[derive(Debug, Clone, PartialEq)]
+enum Topic {
+    Arrivals { station_id: String, lines: String },
+    Status { line_id: String },
+    Disruptions { mode: String },
+}
+
+impl FromStr for Topic {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let mut parts = name.splitn(3, ':');
+        match (
+            parts.next(),
+            parts.next().filter(|id| !id.is_empty()),
+            parts.next(),
+        ) {
+            (Some("arrivals"), Some(station_id), lines) => Ok(Topic::Arrivals {
+                station_id: station_id.to_string(),
+                lines: lines.unwrap_or("tube").to_string(),
+            }),
+            (Some("status"), Some(line_id), None) => Ok(Topic::Status {
+                line_id: line_id.to_string(),
+            }),
+            (Some("disruptions"), Some(mode), None) => match parse_modes(mode) {
+                Ok(modes) if modes.len() == 1 => Ok(Topic::Disruptions {
+                    mode: modes[0].clone(),
+                }),
+                Ok(_) => Err(format!("One mode per topic: {}", name)),
+                Err(e) => Err(e.to_string()),
+            },
+            _ => Err(format!("Unknown topic: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
+}
+
+#[derive(Serialize)]
+struct Update<'a, T> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    topic: &'a str,
+    id: &'a str,
+    results: &'a [T],
+}
+
+// Handler for /ws
+async fn websocket(State(state): State<LiveState>, upgrade: WebSocketUpgrade) -> AxumResponse {
+    upgrade.on_upgrade(move |socket| serve_socket(socket, state))
+}
+
+// Clients send `{"op": "subscribe", "topics": [...]}` or `{"op": "unsubscribe", ...}`
+// and receive `subscribed`/`unsubscribed` acknowledgements, then an `update` with
+// each topic's results whenever they change.
+async fn serve_socket(socket: WebSocket, state: LiveState) {
+    let (mut sink, mut incoming) = socket.split();
+    let (outgoing, mut queue) = mpsc::channel::<Message>(OUTGOING_BUFFER);
+
+    // Everything for the client goes through one bounded queue, with pings to keep
+    // idle connections open
+    let writer = tokio::spawn(async move {
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            let message = tokio::select! {
+                message = queue.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = heartbeat.tick() => Message::Ping(Vec::new()),
+            };
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut forwarders: HashMap<String, AbortHandle> = HashMap::new();
+    while let Some(Ok(message)) = incoming.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let reply = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Subscribe { topics }) => {
+                let mut subscribed = Vec::new();
+                let mut errors = Vec::new();
+                for name in topics {
+                    if forwarders.contains_key(&name) {
+                        subscribed.push(name);
+                    } else if forwarders.len() >= MAX_TOPICS {
+                        errors.push(format!(
+                            "Too many topics (at most {}): {}",
+                            MAX_TOPICS, name
+                        ));
+                    } else {
+                        match name.parse::<Topic>() {
+                            Ok(topic) => {
+                                let forwarder =
+                                    start_forwarder(&state, &name, topic, outgoing.clone());
+                                forwarders.insert(name.clone(), forwarder);
+                                subscribed.push(name);
+                            }
+                            Err(e) => errors.push(e),
+                        }
+                    }
+                }
+                json!({ "type": "subscribed", "topics": subscribed, "errors": errors })
+            }
+            Ok(ClientMessage::Unsubscribe { topics }) => {
+                for name in &topics {
+                    if let Some(forwarder) = forwarders.remove(name) {
+                        forwarder.abort();
+                    }
+                }
+                json!({ "type": "unsubscribed", "topics": topics })
+            }
+            Err(e) => json!({ "type": "error", "message": format!("Invalid message: {}", e) }),
+        };
+
+        if outgoing
+            .send(Message::Text(reply.to_string()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    // Dropping each forwarder's subscription stops pollers nobody else needs
+    for forwarder in forwarders.into_values() {
+        forwarder.abort();
+    }
+    writer.abort();
+}
+
+fn start_forwarder(
+    state: &LiveState,
+    name: &str,
+    topic: Topic,
+    outgoing: mpsc::Sender<Message>,
+) -> AbortHandle {
+    match topic {
+        Topic::Arrivals { station_id, lines } => forward(
+            name,
+            subscribe_arrivals(state, &station_id, &lines),
+            outgoing,
+        ),
+        Topic::Status { line_id } => forward(name, subscribe_status(state, &line_id), outgoing),
+        Topic::Disruptions { mode } => forward(name, subscribe_disruptions(state, &mode), outgoing),
+    }
+}
+
+// Send each new snapshot of a topic to the client. While the client is behind,
+// the poller keeps only the latest snapshot, so a slow client skips intermediate
+// updates rather than having them pile up.
+fn forward<T>(
+    name: &str,
+    mut subscription: Subscription<T>,
+    outgoing: mpsc::Sender<Message>,
+) -> AbortHandle
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    let name = name.to_string();
+    tokio::spawn(async move {
+        loop {
+            let latest = subscription.receiver.borrow_and_update().clone();
+            if let Some(snapshot) = latest {
+                let update = Update {
+                    kind: "update",
+                    topic: &name,
+                    id: &snapshot.id,
+                    results: &snapshot.results,
+                };
+                match serde_json::to_string(&update) {
+                    Ok(text) => {
+                        if outgoing.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => warn!("Failed to serialize update for {}: {}", name, e),
+                }
+            }
+            if subscription.receiver.changed().await.is_err() {
+                break;
+            }
+        }
+    })
+    .abort_handle()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_topics() {
+        assert_eq!(
+            "arrivals:940GZZLUVIC:victoria,district".parse(),
+            Ok(Topic::Arrivals {
+                station_id: "940GZZLUVIC".to_string(),
+                lines: "victoria,district".to_string(),
+            })
+        );
+        assert_eq!(
+            "status:victoria".parse(),
+            Ok(Topic::Status {
+                line_id: "victoria".to_string()
+            })
+        );
+        assert!("disruptions:tube".parse::<Topic>().is_ok());
+        assert!("disruptions:bus".parse::<Topic>().is_err());
+        assert!("arrivals:".parse::<Topic>().is_err());
+        assert!("weather:london".parse::<Topic>().is_err());
+    }
+}
@@ -1,6 +1,12 @@
+pub mod accessibility;
+pub mod analytics;
 pub mod arrivals;
 pub mod disruption;
+pub mod gtfs;
+pub mod lines;
+pub mod live;
 pub mod stations;
+pub mod webhooks;
 
 use chrono::Utc;
 use std::time::Instant;
@@ -19,6 +25,8 @@ pub fn create_metadata(start_time: Instant, query: &str) -> MetaData {
         response_time,
         response_latency: latency_secs,
         query: query.to_string(),
+        freshness: None,
+        gap: None,
     }
 }
 
@@ -28,6 +36,8 @@ pub fn create_response<T>(start_time: Instant, query: &str, results: Vec<T>) ->
     Response {
         context: create_metadata(start_time, query),
         success: true,
+        total: results.len(),
+        next_cursor: None,
         results,
     }
 }
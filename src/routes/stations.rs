@@ -1,18 +1,29 @@
-use axum::{extract::Query, routing::get, Json, Router};
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Router,
+};
 // use polars::prelude::*;
 use serde::Deserialize;
+use std::sync::Arc;
 use std::time::Instant;
 use tracing::info;
 
-use crate::error::AppResult;
-use crate::models::{Response, Station, StationPoint};
+use crate::dataset::{line_mode, Dataset};
+use crate::error::{AppError, AppResult};
+use crate::fare_zones::parse_zone_filter;
+use crate::format::{Formatted, ResponseOptions};
+use crate::models::{NearbyStation, Station, StationMatch, StationPoint};
 use crate::routes::create_response;
 
-pub fn stations_routes() -> Router {
+pub fn stations_routes(dataset: Arc<Dataset>) -> Router {
     Router::new()
         .route("/stations", get(get_stations))
+        .route("/stations/search", get(search_stations))
+        .route("/stations/nearest", get(get_nearest_stations))
         .route("/station-points", get(get_station_points))
         .route("/platforms", get(get_platforms))
+        .with_state(dataset)
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,56 +31,160 @@ pub struct SqlQuery {
     query: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct StationsQuery {
+    query: Option<String>,
+    zone: Option<String>,
+    line: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    q: String,
+}
+
 // Handler for /stations
-// In a real implementation, this would load station data from a file or database
-// For now, we'll return a mock response
-async fn get_stations(Query(params): Query<SqlQuery>) -> AppResult<Json<Response<Station>>> {
+// `zone` keeps stations in any of the given zones (e.g. "1-2"), with boundary
+// stations counting towards each of their zones, and `line` keeps those on a line
+async fn get_stations(
+    options: ResponseOptions,
+    State(dataset): State<Arc<Dataset>>,
+    Query(params): Query<StationsQuery>,
+) -> AppResult<Formatted<Station>> {
     let start_time = Instant::now();
     let query = params
         .query
         .unwrap_or_else(|| "SELECT * FROM self;".to_string());
 
-    info!("Received query={}", query);
-
-    // In a real implementation, we would load the data from a file or database
-    // For now, let's create some sample data
-    let stations = vec![
-        Station {
-            station_unique_id: "940GZZLUASL".to_string(),
-            station_name: "Arsenal".to_string(),
-            fare_zones: Some("2".to_string()),
-            hub_naptan_code: None,
-            wifi: Some(true),
-            outside_station_unique_id: Some("490G000ASL".to_string()),
-            lat: Some(51.5586),
-            lon: Some(-0.1059),
-            lines: Some(vec!["piccadilly".to_string()]),
-        },
-        Station {
-            station_unique_id: "940GZZLUBKG".to_string(),
-            station_name: "Barking".to_string(),
-            fare_zones: Some("4".to_string()),
-            hub_naptan_code: None,
-            wifi: Some(true),
-            outside_station_unique_id: Some("490G000BKG".to_string()),
-            lat: Some(51.5396),
-            lon: Some(0.0813),
-            lines: Some(vec![
-                "district".to_string(),
-                "hammersmith-city".to_string(),
-                "overground".to_string(),
-            ]),
-        },
-    ];
+    info!(
+        "Received query={}, zone={:?}, line={:?}",
+        query, params.zone, params.line
+    );
+
+    let zones = params
+        .zone
+        .as_deref()
+        .map(parse_zone_filter)
+        .transpose()
+        .map_err(AppError::ParseError)?;
+
+    let stations = dataset
+        .stations
+        .iter()
+        .filter(|station| {
+            zones.as_ref().is_none_or(|zones| {
+                station
+                    .fare_zones
+                    .as_ref()
+                    .is_some_and(|fare_zones| fare_zones.contains_any(zones))
+            })
+        })
+        .filter(|station| {
+            params
+                .line
+                .as_ref()
+                .is_none_or(|line| station.lines.as_deref().unwrap_or_default().contains(line))
+        })
+        .cloned()
+        .collect();
 
     let response = create_response(start_time, &query, stations);
-    Ok(Json(response))
+    Ok(options.respond(response))
+}
+
+// Handler for /stations/search
+// Ranks stations by how well their name (or one of their aliases) matches `q`. Every
+// match is returned, best first, for `limit=`/`offset=`/`cursor=` to page through.
+async fn search_stations(
+    options: ResponseOptions,
+    State(dataset): State<Arc<Dataset>>,
+    Query(params): Query<SearchQuery>,
+) -> AppResult<Formatted<StationMatch>> {
+    let start_time = Instant::now();
+
+    info!("Received q={}", params.q);
+
+    let matches = dataset
+        .search
+        .search(&dataset.stations, &params.q, usize::MAX);
+
+    let response = create_response(start_time, &params.q, matches);
+    Ok(options.respond(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NearestQuery {
+    lat: f64,
+    lon: f64,
+    k: Option<usize>,
+    max_m: Option<f64>,
+    line: Option<String>,
+    mode: Option<String>,
+}
+
+// Handler for /stations/nearest
+// Uses the dataset's spatial index, so only the closest candidates are examined
+async fn get_nearest_stations(
+    options: ResponseOptions,
+    State(dataset): State<Arc<Dataset>>,
+    Query(params): Query<NearestQuery>,
+) -> AppResult<Formatted<NearbyStation>> {
+    let start_time = Instant::now();
+    let query = format!("lat={},lon={}", params.lat, params.lon);
+    let k = params.k.unwrap_or(5).min(100);
+
+    info!(
+        "Received {}, k={}, max_m={:?}, line={:?}, mode={:?}",
+        query, k, params.max_m, params.line, params.mode
+    );
+
+    if !(-90.0..=90.0).contains(&params.lat) || !(-180.0..=180.0).contains(&params.lon) {
+        return Err(AppError::ParseError(format!(
+            "Invalid coordinates: {}",
+            query
+        )));
+    }
+
+    let serves = |station: &Station| {
+        let lines = station.lines.as_deref().unwrap_or_default();
+        let line_ok = params
+            .line
+            .as_ref()
+            .is_none_or(|line| lines.iter().any(|l| l == line));
+        let mode_ok = params
+            .mode
+            .as_ref()
+            .is_none_or(|mode| lines.iter().any(|l| line_mode(l) == Some(mode.as_str())));
+        line_ok && mode_ok
+    };
+
+    let nearby = dataset
+        .spatial
+        .nearest(
+            &dataset.stations,
+            params.lat,
+            params.lon,
+            k,
+            params.max_m,
+            serves,
+        )
+        .into_iter()
+        .map(|(i, distance)| NearbyStation {
+            distance,
+            station: dataset.stations[i].clone(),
+        })
+        .collect();
+
+    let response = create_response(start_time, &query, nearby);
+    Ok(options.respond(response))
 }
 
 // Handler for /station-points
 async fn get_station_points(
+    options: ResponseOptions,
+    State(dataset): State<Arc<Dataset>>,
     Query(params): Query<SqlQuery>,
-) -> AppResult<Json<Response<StationPoint>>> {
+) -> AppResult<Formatted<StationPoint>> {
     let start_time = Instant::now();
     let query = params
         .query
@@ -77,48 +192,16 @@ async fn get_station_points(
 
     info!("Received query={}", query);
 
-    // Create mock station points
-    let station_points = vec![
-        StationPoint {
-            unique_id: "ASL-1".to_string(),
-            station_unique_id: "940GZZLUASL".to_string(),
-            area_name: "Arsenal Station".to_string(),
-            area_id: 1,
-            level: 0,
-            lat: 51.5586,
-            lon: -0.1059,
-            friendly_name: "Arsenal Station Entrance".to_string(),
-        },
-        StationPoint {
-            unique_id: "ASL-2".to_string(),
-            station_unique_id: "940GZZLUASL".to_string(),
-            area_name: "Arsenal Station Platform".to_string(),
-            area_id: 2,
-            level: -1,
-            lat: 51.5587,
-            lon: -0.1060,
-            friendly_name: "Arsenal Station Platform".to_string(),
-        },
-        StationPoint {
-            unique_id: "BKG-1".to_string(),
-            station_unique_id: "940GZZLUBKG".to_string(),
-            area_name: "Barking Station".to_string(),
-            area_id: 3,
-            level: 0,
-            lat: 51.5396,
-            lon: 0.0813,
-            friendly_name: "Barking Station Entrance".to_string(),
-        },
-    ];
-
-    let response = create_response(start_time, &query, station_points);
-    Ok(Json(response))
+    let response = create_response(start_time, &query, dataset.station_points.clone());
+    Ok(options.respond(response))
 }
 
 // Handler for /platforms
 async fn get_platforms(
+    options: ResponseOptions,
+    State(dataset): State<Arc<Dataset>>,
     Query(params): Query<SqlQuery>,
-) -> AppResult<Json<Response<serde_json::Value>>> {
+) -> AppResult<Formatted<serde_json::Value>> {
     let start_time = Instant::now();
     let query = params
         .query
@@ -126,35 +209,6 @@ async fn get_platforms(
 
     info!("Received query={}", query);
 
-    // For platforms, we'll just return a mock JSON response
-    let mut platforms = Vec::new();
-
-    // Create a few sample platforms
-    let platform1 = serde_json::json!({
-        "PlatformUniqueId": "ASL-P1",
-        "StationUniqueId": "940GZZLUASL",
-        "PlatformNumber": "1",
-        "CardinalDirection": "NB",
-        "PlatformNaptanCode": "940GZZLUASL1",
-        "PlatformFriendlyName": "Northbound Platform 1",
-        "IsCustomerFacing": true,
-        "HasServiceInterchange": false
-    });
-
-    let platform2 = serde_json::json!({
-        "PlatformUniqueId": "ASL-P2",
-        "StationUniqueId": "940GZZLUASL",
-        "PlatformNumber": "2",
-        "CardinalDirection": "SB",
-        "PlatformNaptanCode": "940GZZLUASL2",
-        "PlatformFriendlyName": "Southbound Platform 2",
-        "IsCustomerFacing": true,
-        "HasServiceInterchange": false
-    });
-
-    platforms.push(platform1);
-    platforms.push(platform2);
-
-    let response = create_response(start_time, &query, platforms);
-    Ok(Json(response))
+    let response = create_response(start_time, &query, dataset.platforms.clone());
+    Ok(options.respond(response))
 }
@@ -0,0 +1,117 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{delete, get},
+    Json, Router,
+};
+use std::env;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+use crate::error::{AppError, AppResult};
+use crate::format::{Formatted, ResponseOptions};
+use crate::models::Response;
+use crate::routes::create_response;
+use crate::webhooks::{DeadLetter, Webhook, WebhookConfig, Webhooks};
+
+#[derive(Clone)]
+pub struct WebhookState {
+    webhooks: Arc<Webhooks>,
+    // The admin API is disabled unless TB8_ADMIN_TOKEN is set
+    admin_token: Option<String>,
+}
+
+pub fn webhook_routes(webhooks: Arc<Webhooks>) -> Router {
+    let state = WebhookState {
+        webhooks,
+        admin_token: env::var("TB8_ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+    };
+
+    Router::new()
+        .route("/admin/webhooks", get(list_webhooks).post(register_webhook))
+        .route("/admin/webhooks/:id", delete(remove_webhook))
+        .route("/admin/webhooks/dead-letters", get(list_dead_letters))
+        .with_state(state)
+}
+
+// Requests must carry `Authorization: Bearer <TB8_ADMIN_TOKEN>`
+fn authorize(state: &WebhookState, headers: &HeaderMap) -> AppResult<()> {
+    let Some(token) = &state.admin_token else {
+        return Err(AppError::Unauthorized(
+            "Admin API is disabled (TB8_ADMIN_TOKEN is not set)".to_string(),
+        ));
+    };
+    let given = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    // Compare in constant time
+    let matches = given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::Unauthorized("Invalid admin token".to_string()))
+    }
+}
+
+// Handler for GET /admin/webhooks
+async fn list_webhooks(
+    options: ResponseOptions,
+    State(state): State<WebhookState>,
+    headers: HeaderMap,
+) -> AppResult<Formatted<Webhook>> {
+    let start_time = Instant::now();
+    authorize(&state, &headers)?;
+
+    let webhooks = state.webhooks.list();
+    Ok(options.respond(create_response(start_time, "webhooks", webhooks)))
+}
+
+// Handler for POST /admin/webhooks
+async fn register_webhook(
+    State(state): State<WebhookState>,
+    headers: HeaderMap,
+    Json(config): Json<WebhookConfig>,
+) -> AppResult<(StatusCode, Json<Response<Webhook>>)> {
+    let start_time = Instant::now();
+    authorize(&state, &headers)?;
+
+    let webhook = state.webhooks.register(config)?;
+    info!("Registered webhook {} for {}", webhook.id, webhook.url);
+    let response = create_response(start_time, &webhook.id.clone(), vec![webhook]);
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+// Handler for DELETE /admin/webhooks/:id
+async fn remove_webhook(
+    State(state): State<WebhookState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> AppResult<StatusCode> {
+    authorize(&state, &headers)?;
+
+    state.webhooks.remove(&id)?;
+    info!("Removed webhook {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Handler for GET /admin/webhooks/dead-letters
+async fn list_dead_letters(
+    options: ResponseOptions,
+    State(state): State<WebhookState>,
+    headers: HeaderMap,
+) -> AppResult<Formatted<DeadLetter>> {
+    let start_time = Instant::now();
+    authorize(&state, &headers)?;
+
+    let dead_letters = state.webhooks.dead_letters();
+    Ok(options.respond(create_response(start_time, "dead-letters", dead_letters)))
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+use strsim::{jaro_winkler, normalized_damerau_levenshtein};
+use tracing::{info, warn};
+
+use crate::models::{Station, StationMatch};
+
+// Aliases that are always available, keyed by the normalised alias text and
+// pointing at a station unique id (which is also its NaPTAN id)
+const DEFAULT_ALIASES: &[(&str, &str)] = &[
+    ("kings x", "940GZZLUKSX"),
+    ("kx", "940GZZLUKSX"),
+    ("st pancras", "940GZZLUKSX"),
+    ("kings cross", "940GZZLUKSX"),
+    ("stratford international", "940GZZLUSTD"),
+];
+
+// Words that carry no information when matching station names
+const NOISE_WORDS: &[&str] = &["station", "underground", "tube"];
+
+// Scores below this are not returned
+const MIN_SCORE: f64 = 0.6;
+
+// Load the alias table: the defaults, extended (or overridden) by a JSON object of
+// `{ "alias": "stationUniqueId" }` read from the file at TB8_STATION_ALIASES
+pub fn load_aliases() -> HashMap<String, String> {
+    let mut aliases: HashMap<String, String> = DEFAULT_ALIASES
+        .iter()
+        .map(|(alias, id)| (normalise(alias), id.to_string()))
+        .collect();
+
+    if let Ok(path) = env::var("TB8_STATION_ALIASES") {
+        let extra = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                serde_json::from_str::<HashMap<String, String>>(&text).map_err(|e| e.to_string())
+            });
+        match extra {
+            Ok(extra) => {
+                info!("Loaded {} station aliases from {}", extra.len(), path);
+                aliases.extend(extra.into_iter().map(|(alias, id)| (normalise(&alias), id)));
+            }
+            Err(e) => warn!("Ignoring station aliases file {}: {}", path, e),
+        }
+    }
+
+    aliases
+}
+
+// Normalise a station name or query for comparison: lowercase, drop apostrophes,
+// turn other punctuation into spaces, and canonicalise common abbreviations
+pub fn normalise(text: &str) -> String {
+    let cleaned: String = text
+        .to_lowercase()
+        .replace('&', " and ")
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '\u{2019}' | '`'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    cleaned
+        .split_whitespace()
+        .filter(|word| !NOISE_WORDS.contains(word))
+        .map(|word| match word {
+            "saint" => "st",
+            "street" => "st",
+            "road" => "rd",
+            other => other,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+struct Candidate {
+    text: String,
+    station_index: usize,
+    matched_on: String,
+}
+
+// Search index over station names and aliases, built once alongside the dataset
+pub struct StationSearch {
+    candidates: Vec<Candidate>,
+}
+
+impl StationSearch {
+    pub fn new(stations: &[Station], aliases: HashMap<String, String>) -> Self {
+        let mut candidates: Vec<Candidate> = stations
+            .iter()
+            .enumerate()
+            .map(|(station_index, station)| Candidate {
+                text: normalise(&station.station_name),
+                station_index,
+                matched_on: station.station_name.clone(),
+            })
+            .collect();
+
+        for (alias, id) in aliases {
+            match stations.iter().position(|s| s.station_unique_id == id) {
+                Some(station_index) => candidates.push(Candidate {
+                    matched_on: alias.clone(),
+                    text: alias,
+                    station_index,
+                }),
+                None => warn!("Alias '{}' refers to unknown station {}", alias, id),
+            }
+        }
+
+        Self { candidates }
+    }
+
+    // Rank stations against the query, best match first, one entry per station.
+    // `stations` must be the same slice the index was built from.
+    pub fn search(&self, stations: &[Station], query: &str, limit: usize) -> Vec<StationMatch> {
+        let query = normalise(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut best: HashMap<usize, (f64, &Candidate)> = HashMap::new();
+        for candidate in &self.candidates {
+            let score = score(&query, &candidate.text);
+            if score < MIN_SCORE {
+                continue;
+            }
+            let entry = best
+                .entry(candidate.station_index)
+                .or_insert((score, candidate));
+            if score > entry.0 {
+                *entry = (score, candidate);
+            }
+        }
+
+        let mut matches: Vec<StationMatch> = best
+            .into_values()
+            .map(|(score, candidate)| {
+                let station = stations[candidate.station_index].clone();
+                StationMatch {
+                    naptan_id: station.station_unique_id.clone(),
+                    score,
+                    matched_on: candidate.matched_on.clone(),
+                    station,
+                }
+            })
+            .collect();
+
+        matches.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.station.station_name.cmp(&b.station.station_name))
+        });
+        matches.truncate(limit);
+        matches
+    }
+}
+
+// Score a normalised query against a normalised candidate, from 0.0 to 1.0.
+// Exact and prefix matches rank above token matches, which rank above typos.
+fn score(query: &str, candidate: &str) -> f64 {
+    if query == candidate {
+        return 1.0;
+    }
+    if candidate.starts_with(query) {
+        return 0.95;
+    }
+
+    let candidate_tokens: Vec<&str> = candidate.split(' ').collect();
+    let query_tokens: Vec<&str> = query.split(' ').collect();
+
+    if query_tokens
+        .iter()
+        .all(|q| candidate_tokens.iter().any(|c| c.starts_with(q)))
+    {
+        return 0.85;
+    }
+
+    // Typo tolerance: each query token against its closest candidate token
+    let token_score = query_tokens
+        .iter()
+        .map(|q| {
+            candidate_tokens
+                .iter()
+                .map(|c| normalized_damerau_levenshtein(q, c))
+                .fold(0.0, f64::max)
+        })
+        .sum::<f64>()
+        / query_tokens.len() as f64;
+
+    token_score.max(jaro_winkler(query, candidate)) * 0.8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+
+    #[test]
+    fn test_normalise() {
+        assert_eq!(normalise("Shepherd's Bush"), "shepherds bush");
+        assert_eq!(
+            normalise("King's Cross St. Pancras"),
+            "kings cross st pancras"
+        );
+        assert_eq!(
+            normalise("Elephant & Castle Station"),
+            "elephant and castle"
+        );
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        let dataset = Dataset::load();
+
+        let results = dataset
+            .search
+            .search(&dataset.stations, "Shepherds Bush", 10);
+        assert_eq!(results[0].naptan_id, "940GZZLUSBC");
+        assert_eq!(results[1].naptan_id, "940GZZLUSBM");
+
+        let results = dataset.search.search(&dataset.stations, "kings x", 10);
+        assert_eq!(results[0].naptan_id, "940GZZLUKSX");
+
+        let results = dataset.search.search(&dataset.stations, "stockwel", 10);
+        assert_eq!(results[0].naptan_id, "940GZZLUSKW");
+
+        let results = dataset.search.search(&dataset.stations, "brixtin", 10);
+        assert_eq!(results[0].naptan_id, "940GZZLUBXN");
+    }
+}
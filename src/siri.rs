@@ -0,0 +1,665 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use quick_xml::events::{BytesDecl, BytesText, Event};
+use quick_xml::Writer;
+use std::collections::BTreeSet;
+use std::io;
+
+use crate::changes::disruption_ids;
+use crate::error::{AppError, AppResult};
+use crate::gtfs_rt::group_by_vehicle;
+use crate::models::{Disruption, MetaData, Prediction};
+
+// SIRI 2.0 renderings of disruptions (SIRI-SX, situation exchange) and predictions
+// (SIRI-ET, estimated timetable). Elements are written in the order the SIRI schema's
+// sequences require, and only the optional ones TfL data can fill are included.
+
+const SIRI_NAMESPACE: &str = "http://www.siri.org.uk/siri";
+const SIRI_VERSION: &str = "2.0";
+const PRODUCER_REF: &str = "TfL";
+
+type XmlWriter = Writer<Vec<u8>>;
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn element(writer: &mut XmlWriter, name: &str, text: &str) -> io::Result<()> {
+    writer
+        .create_element(name)
+        .write_text_content(BytesText::new(text))?;
+    Ok(())
+}
+
+fn optional_element(writer: &mut XmlWriter, name: &str, text: Option<&str>) -> io::Result<()> {
+    match text {
+        Some(text) => element(writer, name, text),
+        None => Ok(()),
+    }
+}
+
+// The <Siri><ServiceDelivery> envelope around a single delivery
+fn document(
+    context: &MetaData,
+    delivery: &str,
+    content: impl FnOnce(&mut XmlWriter) -> io::Result<()>,
+) -> AppResult<Vec<u8>> {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    let response_timestamp = timestamp(context.response_time);
+
+    writer
+        .write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))
+        .and_then(|_| {
+            writer
+                .create_element("Siri")
+                .with_attribute(("xmlns", SIRI_NAMESPACE))
+                .with_attribute(("version", SIRI_VERSION))
+                .write_inner_content(|writer| {
+                    writer
+                        .create_element("ServiceDelivery")
+                        .write_inner_content(|writer| {
+                            element(writer, "ResponseTimestamp", &response_timestamp)?;
+                            element(writer, "ProducerRef", PRODUCER_REF)?;
+                            writer
+                                .create_element(delivery)
+                                .with_attribute(("version", SIRI_VERSION))
+                                .write_inner_content(|writer| {
+                                    element(writer, "ResponseTimestamp", &response_timestamp)?;
+                                    content(writer)
+                                })?;
+                            Ok(())
+                        })?;
+                    Ok(())
+                })
+        })
+        .map_err(|e| AppError::InternalError(format!("Failed to write SIRI: {}", e)))?;
+
+    Ok(writer.into_inner())
+}
+
+// SIRI-SX: one PtSituationElement per disruption
+pub fn situation_exchange(context: &MetaData, disruptions: &[Disruption]) -> AppResult<Vec<u8>> {
+    document(context, "SituationExchangeDelivery", |writer| {
+        writer
+            .create_element("Situations")
+            .write_inner_content(|writer| {
+                for (id, disruption) in disruption_ids(disruptions).iter().zip(disruptions) {
+                    situation(writer, context, id, disruption)?;
+                }
+                Ok(())
+            })?;
+        Ok(())
+    })
+}
+
+fn situation(
+    writer: &mut XmlWriter,
+    context: &MetaData,
+    id: &str,
+    disruption: &Disruption,
+) -> io::Result<()> {
+    let created = disruption.created.unwrap_or(context.request_time);
+    let start = disruption
+        .affected_routes
+        .iter()
+        .filter_map(|route| route.valid_from)
+        .min()
+        .unwrap_or(created);
+    let end = disruption
+        .affected_routes
+        .iter()
+        .filter_map(|route| route.valid_to)
+        .max();
+    let lines: BTreeSet<&String> = disruption
+        .affected_routes
+        .iter()
+        .filter_map(|route| route.line_id.as_ref())
+        .collect();
+    let planned = disruption.category.as_deref() == Some("PlannedWork");
+    let summary = disruption
+        .summary
+        .as_deref()
+        .or(disruption.category_description.as_deref())
+        .or(disruption.description.as_deref())
+        .unwrap_or_default();
+
+    writer
+        .create_element("PtSituationElement")
+        .write_inner_content(|writer| {
+            element(writer, "CreationTime", &timestamp(created))?;
+            element(writer, "ParticipantRef", PRODUCER_REF)?;
+            element(writer, "SituationNumber", id)?;
+            writer
+                .create_element("Source")
+                .write_inner_content(|writer| element(writer, "SourceType", "feed"))?;
+            if let Some(last_update) = disruption.last_update {
+                element(writer, "VersionedAtTime", &timestamp(last_update))?;
+            }
+            element(writer, "Progress", "open")?;
+            writer
+                .create_element("ValidityPeriod")
+                .write_inner_content(|writer| {
+                    element(writer, "StartTime", &timestamp(start))?;
+                    optional_element(writer, "EndTime", end.map(timestamp).as_deref())
+                })?;
+            element(writer, "MiscellaneousReason", "unknown")?;
+            element(writer, "Severity", severity(disruption))?;
+            element(
+                writer,
+                "ReportType",
+                if planned { "general" } else { "incident" },
+            )?;
+            element(writer, "Planned", &planned.to_string())?;
+            element(writer, "Summary", summary)?;
+            optional_element(writer, "Description", disruption.description.as_deref())?;
+
+            if lines.is_empty() && disruption.affected_stops.is_empty() {
+                return Ok(());
+            }
+            writer
+                .create_element("Affects")
+                .write_inner_content(|writer| {
+                    if !lines.is_empty() {
+                        writer
+                            .create_element("Networks")
+                            .write_inner_content(|writer| {
+                                writer
+                                    .create_element("AffectedNetwork")
+                                    .write_inner_content(|writer| {
+                                        for line in &lines {
+                                            writer
+                                                .create_element("AffectedLine")
+                                                .write_inner_content(|writer| {
+                                                    element(writer, "LineRef", line)
+                                                })?;
+                                        }
+                                        Ok(())
+                                    })?;
+                                Ok(())
+                            })?;
+                    }
+                    if !disruption.affected_stops.is_empty() {
+                        writer
+                            .create_element("StopPoints")
+                            .write_inner_content(|writer| {
+                                for stop in &disruption.affected_stops {
+                                    writer
+                                        .create_element("AffectedStopPoint")
+                                        .write_inner_content(|writer| {
+                                            optional_element(
+                                                writer,
+                                                "StopPointRef",
+                                                stop.naptan_id.as_deref().or(stop.id.as_deref()),
+                                            )?;
+                                            optional_element(
+                                                writer,
+                                                "StopPointName",
+                                                stop.common_name.as_deref(),
+                                            )
+                                        })?;
+                                }
+                                Ok(())
+                            })?;
+                    }
+                    Ok(())
+                })?;
+            Ok(())
+        })?;
+    Ok(())
+}
+
+fn severity(disruption: &Disruption) -> &'static str {
+    match disruption.closure_text.as_deref() {
+        Some("closed") | Some("fullClosure") | Some("suspended") => "verySevere",
+        Some("partClosure") | Some("partSuspended") | Some("severeDelays") => "severe",
+        Some("minorDelays") | Some("reducedService") => "slight",
+        _ => "unknown",
+    }
+}
+
+// SIRI-ET: one EstimatedVehicleJourney per vehicle on each line, calling at the
+// stops it has predictions for
+pub fn estimated_timetable(context: &MetaData, predictions: &[Prediction]) -> AppResult<Vec<u8>> {
+    let journeys = group_by_vehicle(predictions);
+
+    document(context, "EstimatedTimetableDelivery", |writer| {
+        writer
+            .create_element("EstimatedJourneyVersionFrame")
+            .write_inner_content(|writer| {
+                element(writer, "RecordedAtTime", &timestamp(context.request_time))?;
+                for ((line_id, vehicle_id), calls) in &journeys {
+                    let first = calls[0];
+                    writer
+                        .create_element("EstimatedVehicleJourney")
+                        .write_inner_content(|writer| {
+                            element(writer, "LineRef", line_id)?;
+                            element(
+                                writer,
+                                "DirectionRef",
+                                first.direction.as_deref().unwrap_or("unknown"),
+                            )?;
+                            element(
+                                writer,
+                                "EstimatedVehicleJourneyCode",
+                                &format!("{}-{}", line_id, vehicle_id),
+                            )?;
+                            optional_element(
+                                writer,
+                                "PublishedLineName",
+                                first.line_name.as_deref(),
+                            )?;
+                            optional_element(
+                                writer,
+                                "DestinationRef",
+                                first.destination_naptan_id.as_deref(),
+                            )?;
+                            optional_element(
+                                writer,
+                                "DestinationName",
+                                first
+                                    .destination_name
+                                    .as_deref()
+                                    .or(first.towards.as_deref()),
+                            )?;
+                            element(writer, "Monitored", "true")?;
+                            element(writer, "VehicleRef", vehicle_id)?;
+                            writer
+                                .create_element("EstimatedCalls")
+                                .write_inner_content(|writer| {
+                                    for call in calls {
+                                        estimated_call(writer, call)?;
+                                    }
+                                    Ok(())
+                                })?;
+                            Ok(())
+                        })?;
+                }
+                Ok(())
+            })?;
+        Ok(())
+    })
+}
+
+fn estimated_call(writer: &mut XmlWriter, prediction: &Prediction) -> io::Result<()> {
+    writer
+        .create_element("EstimatedCall")
+        .write_inner_content(|writer| {
+            element(
+                writer,
+                "StopPointRef",
+                prediction.naptan_id.as_deref().unwrap_or_default(),
+            )?;
+            optional_element(writer, "StopPointName", prediction.station_name.as_deref())?;
+            optional_element(
+                writer,
+                "ExpectedArrivalTime",
+                prediction.expected_arrival.map(timestamp).as_deref(),
+            )?;
+            optional_element(
+                writer,
+                "ArrivalPlatformName",
+                prediction.platform_name.as_deref(),
+            )
+        })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::create_metadata;
+    use quick_xml::events::Event;
+    use quick_xml::{Reader, XmlVersion};
+    use std::time::Instant;
+
+    // A subset of the published SIRI 2.0 XSD covering the elements we write
+    const SCHEMA: &str = include_str!("../tests/fixtures/siri-2.0-subset.xsd");
+
+    #[derive(Default)]
+    struct Node {
+        name: String,
+        attributes: Vec<(String, String)>,
+        text: String,
+        children: Vec<Node>,
+    }
+
+    impl Node {
+        fn attribute(&self, name: &str) -> Option<&str> {
+            self.attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        }
+
+        fn child(&self, name: &str) -> Option<&Node> {
+            self.children.iter().find(|child| child.name == name)
+        }
+    }
+
+    fn node(e: &quick_xml::events::BytesStart) -> Node {
+        Node {
+            name: e.local_name().into_inner().to_string(),
+            attributes: e
+                .attributes()
+                .map(|attribute| {
+                    let attribute = attribute.unwrap();
+                    (
+                        attribute.key.into_inner().to_string(),
+                        attribute
+                            .normalized_value(XmlVersion::Implicit1_0)
+                            .unwrap()
+                            .into_owned(),
+                    )
+                })
+                .collect(),
+            ..Node::default()
+        }
+    }
+
+    // Elements by local name, so the schema's xsd: prefix drops away
+    fn parse(xml: &[u8]) -> Node {
+        let mut reader = Reader::from_reader(xml);
+        reader.config_mut().trim_text(true);
+        let mut stack = vec![Node::default()];
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buf).unwrap() {
+                Event::Start(e) => stack.push(node(&e)),
+                Event::Empty(e) => stack.last_mut().unwrap().children.push(node(&e)),
+                Event::Text(e) => stack.last_mut().unwrap().text.push_str(&e.xml10_content()),
+                Event::End(_) => {
+                    let node = stack.pop().unwrap();
+                    stack.last_mut().unwrap().children.push(node);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        stack.pop().unwrap().children.remove(0)
+    }
+
+    fn occurs(particle: &Node) -> (usize, usize) {
+        let min = particle
+            .attribute("minOccurs")
+            .map_or(1, |n| n.parse().unwrap());
+        let max = match particle.attribute("maxOccurs") {
+            Some("unbounded") => usize::MAX,
+            Some(n) => n.parse().unwrap(),
+            None => 1,
+        };
+        (min, max)
+    }
+
+    // Just enough of XSD to check a document against the fixture: named and inline
+    // types, sequences and choices with occurrence bounds, simple content,
+    // enumerations and attributes. Panics at the first thing that doesn't conform.
+    struct Schema(Node);
+
+    impl Schema {
+        fn global(&self, kind: &str, name: &str) -> Option<&Node> {
+            self.0
+                .children
+                .iter()
+                .find(|decl| decl.name == kind && decl.attribute("name") == Some(name))
+        }
+
+        fn validate(&self, document: &Node) {
+            let decl = self
+                .global("element", &document.name)
+                .unwrap_or_else(|| panic!("{} is not a SIRI root element", document.name));
+            assert_eq!(
+                document.attribute("xmlns"),
+                self.0.attribute("targetNamespace"),
+                "{} is not in the SIRI namespace",
+                document.name
+            );
+            self.element(decl, document);
+        }
+
+        fn element(&self, decl: &Node, node: &Node) {
+            match decl.attribute("type") {
+                Some(type_name) => match self.global("complexType", type_name) {
+                    Some(complex) => self.complex(complex, node),
+                    None => self.simple(type_name, node),
+                },
+                None => match decl.child("complexType") {
+                    Some(complex) => self.complex(complex, node),
+                    None => self.simple("xsd:string", node),
+                },
+            }
+        }
+
+        fn simple(&self, type_name: &str, node: &Node) {
+            assert!(
+                node.children.is_empty(),
+                "{} should only contain text",
+                node.name
+            );
+            self.value(type_name, &node.text, &node.name);
+        }
+
+        fn value(&self, type_name: &str, value: &str, context: &str) {
+            let valid = match type_name {
+                "xsd:string" => true,
+                "xsd:NMTOKEN" => {
+                    !value.is_empty()
+                        && value
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || ".-_:".contains(c))
+                }
+                "xsd:dateTime" => DateTime::parse_from_rfc3339(value).is_ok(),
+                "xsd:boolean" => matches!(value, "true" | "false" | "1" | "0"),
+                "xsd:integer" => value.parse::<i64>().is_ok(),
+                "xsd:nonNegativeInteger" => value.parse::<u64>().is_ok(),
+                "xsd:positiveInteger" => value.parse::<u64>().is_ok_and(|n| n > 0),
+                _ => {
+                    let restriction = self
+                        .global("simpleType", type_name)
+                        .and_then(|simple| simple.child("restriction"))
+                        .unwrap_or_else(|| panic!("unknown type {}", type_name));
+                    self.value(restriction.attribute("base").unwrap(), value, context);
+                    let values: Vec<_> = restriction
+                        .children
+                        .iter()
+                        .filter(|facet| facet.name == "enumeration")
+                        .filter_map(|facet| facet.attribute("value"))
+                        .collect();
+                    values.is_empty() || values.contains(&value)
+                }
+            };
+            assert!(
+                valid,
+                "{:?} in {} is not a valid {}",
+                value, context, type_name
+            );
+        }
+
+        fn complex(&self, complex: &Node, node: &Node) {
+            let content = complex.child("simpleContent");
+            let extension = content.and_then(|content| content.child("extension"));
+            self.attributes(extension.unwrap_or(complex), node);
+
+            if let Some(extension) = extension {
+                return self.simple(extension.attribute("base").unwrap(), node);
+            }
+            assert!(
+                node.text.is_empty(),
+                "{} should not contain text",
+                node.name
+            );
+
+            let particle = complex
+                .children
+                .iter()
+                .find(|child| child.name == "sequence" || child.name == "choice");
+            let end = match particle {
+                Some(particle) => self
+                    .consume(particle, &node.children, 0)
+                    .unwrap_or_else(|| panic!("{} content does not match its type", node.name)),
+                None => 0,
+            };
+            if let Some(child) = node.children.get(end) {
+                panic!("{} not allowed here in {}", child.name, node.name);
+            }
+        }
+
+        fn attributes(&self, declarations: &Node, node: &Node) {
+            let declared: Vec<_> = declarations
+                .children
+                .iter()
+                .filter(|decl| decl.name == "attribute")
+                .collect();
+            for (name, value) in &node.attributes {
+                if name == "xmlns" || name.starts_with("xmlns:") {
+                    continue;
+                }
+                let decl = declared
+                    .iter()
+                    .find(|decl| decl.attribute("name") == Some(name.as_str()))
+                    .unwrap_or_else(|| panic!("{} has no attribute {}", node.name, name));
+                self.value(decl.attribute("type").unwrap(), value, &node.name);
+            }
+            for decl in declared {
+                let name = decl.attribute("name").unwrap();
+                assert!(
+                    decl.attribute("use") != Some("required") || node.attribute(name).is_some(),
+                    "{} is missing attribute {}",
+                    node.name,
+                    name
+                );
+            }
+        }
+
+        // Match as many repetitions of `particle` as the children allow, starting at
+        // `position`, and return where they end; None if fewer than minOccurs match
+        fn consume(&self, particle: &Node, children: &[Node], position: usize) -> Option<usize> {
+            let (min, max) = occurs(particle);
+            let mut position = position;
+            let mut count = 0;
+            while count < max {
+                match self.consume_once(particle, children, position) {
+                    Some(next) if next > position => {
+                        position = next;
+                        count += 1;
+                    }
+                    // An empty match stands in for any repetitions still required
+                    Some(_) => {
+                        count = count.max(min);
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            (count >= min).then_some(position)
+        }
+
+        fn consume_once(
+            &self,
+            particle: &Node,
+            children: &[Node],
+            position: usize,
+        ) -> Option<usize> {
+            match particle.name.as_str() {
+                "element" => {
+                    let child = children.get(position)?;
+                    if Some(child.name.as_str()) != particle.attribute("name") {
+                        return None;
+                    }
+                    self.element(particle, child);
+                    Some(position + 1)
+                }
+                "sequence" => particle
+                    .children
+                    .iter()
+                    .try_fold(position, |position, item| {
+                        self.consume(item, children, position)
+                    }),
+                "choice" => particle
+                    .children
+                    .iter()
+                    .filter_map(|alternative| self.consume(alternative, children, position))
+                    .max(),
+                other => panic!("unsupported particle {}", other),
+            }
+        }
+    }
+
+    fn validate(xml: &[u8]) {
+        Schema(parse(SCHEMA.as_bytes())).validate(&parse(xml));
+    }
+
+    #[test]
+    fn test_situation_exchange_validates() {
+        let disruptions: Vec<Disruption> = serde_json::from_value(serde_json::json!([{
+            "category": "RealTime",
+            "description": "Severe delays <due to> a signal failure",
+            "closureText": "severeDelays",
+            "created": "2024-01-01T08:00:00Z",
+            "affectedRoutes": [{ "lineId": "victoria" }],
+            "affectedStops": [{ "naptanId": "940GZZLUVIC", "commonName": "Victoria" }],
+        }]))
+        .unwrap();
+        let context = create_metadata(Instant::now(), "tube");
+
+        let xml = situation_exchange(&context, &disruptions).unwrap();
+        validate(&xml);
+
+        let text = String::from_utf8(xml).unwrap();
+        assert!(text.contains("<Severity>severe</Severity>"));
+        assert!(text.contains("&lt;due to&gt;"));
+    }
+
+    #[test]
+    fn test_estimated_timetable_validates() {
+        let predictions: Vec<Prediction> = serde_json::from_value(serde_json::json!([
+            { "lineId": "victoria", "lineName": "Victoria", "vehicleId": "201",
+              "naptanId": "940GZZLUVIC", "stationName": "Victoria Underground Station",
+              "platformName": "Southbound - Platform 4", "direction": "outbound",
+              "expectedArrival": "2024-01-01T12:05:00Z" },
+            { "lineId": "victoria", "vehicleId": "201", "naptanId": "940GZZLUOVL",
+              "expectedArrival": "2024-01-01T12:01:00Z" },
+        ]))
+        .unwrap();
+        let context = create_metadata(Instant::now(), "940GZZLUVIC");
+
+        let xml = estimated_timetable(&context, &predictions).unwrap();
+        validate(&xml);
+
+        let text = String::from_utf8(xml).unwrap();
+        assert!(text
+            .contains("<EstimatedVehicleJourneyCode>victoria-201</EstimatedVehicleJourneyCode>"));
+        assert_eq!(text.matches("<EstimatedCall>").count(), 2);
+    }
+
+    fn sample_situation() -> String {
+        let disruptions: Vec<Disruption> = serde_json::from_value(serde_json::json!([{
+            "category": "PlannedWork",
+            "description": "No service between Brixton and Stockwell",
+            "closureText": "partClosure",
+            "affectedRoutes": [{ "lineId": "victoria" }],
+        }]))
+        .unwrap();
+        let context = create_metadata(Instant::now(), "tube");
+        String::from_utf8(situation_exchange(&context, &disruptions).unwrap()).unwrap()
+    }
+
+    #[test]
+    #[should_panic(expected = "PtSituationElement content does not match its type")]
+    fn test_schema_rejects_out_of_order_elements() {
+        let xml = sample_situation()
+            .replace("<Progress>open</Progress>", "")
+            .replace(
+                "<Planned>true</Planned>",
+                "<Planned>true</Planned><Progress>open</Progress>",
+            );
+        validate(xml.as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a valid SeverityEnumeration")]
+    fn test_schema_rejects_unknown_values() {
+        let xml =
+            sample_situation().replace("<Severity>severe</Severity>", "<Severity>bad</Severity>");
+        validate(xml.as_bytes());
+    }
+}
@@ -88,12 +88,24 @@ impl TflClient {
         self.perform_request(&format!("/Line/{}", line_id)).await
     }
 
+    pub async fn get_line_status(&self, line_id: &str) -> AppResult<Vec<Line>> {
+        debug!("Fetching status for line: {}", line_id);
+        self.perform_request(&format!("/Line/{}/Status", line_id))
+            .await
+    }
+
     #[allow(dead_code)]
     pub async fn get_lines_by_mode(&self, mode: &str) -> AppResult<Vec<Line>> {
         debug!("Fetching lines by mode: {}", mode);
         self.perform_request(&format!("/Line/Mode/{}", mode)).await
     }
 
+    pub async fn get_line_statuses_by_mode(&self, mode: &str) -> AppResult<Vec<Line>> {
+        debug!("Fetching line statuses for mode: {}", mode);
+        self.perform_request(&format!("/Line/Mode/{}/Status", mode))
+            .await
+    }
+
     #[allow(dead_code)]
     pub async fn get_arrivals_by_line(&self, line_id: &str) -> AppResult<Vec<Prediction>> {
         debug!("Fetching arrivals for line: {}", line_id);
@@ -111,6 +123,38 @@ impl TflClient {
             .await
     }
 
+    pub async fn get_arrivals_at_stop(&self, stop_id: &str) -> AppResult<Vec<Prediction>> {
+        debug!("Fetching arrivals at stop: {}", stop_id);
+        self.perform_request(&format!("/StopPoint/{}/Arrivals", stop_id))
+            .await
+    }
+
+    pub async fn get_route_sequence(
+        &self,
+        line_id: &str,
+        direction: &str,
+    ) -> AppResult<RouteSequence> {
+        debug!(
+            "Fetching {} route sequence for line: {}",
+            direction, line_id
+        );
+        self.perform_request(&format!("/Line/{}/Route/Sequence/{}", line_id, direction))
+            .await
+    }
+
+    pub async fn get_timetable(
+        &self,
+        line_id: &str,
+        from_stop_id: &str,
+    ) -> AppResult<TimetableResponse> {
+        debug!(
+            "Fetching timetable for line {} from {}",
+            line_id, from_stop_id
+        );
+        self.perform_request(&format!("/Line/{}/Timetable/{}", line_id, from_stop_id))
+            .await
+    }
+
     #[allow(dead_code)]
     pub async fn get_disruptions_by_line(&self, line_id: &str) -> AppResult<Vec<Disruption>> {
         debug!("Fetching disruptions for line: {}", line_id);
@@ -118,6 +162,17 @@ impl TflClient {
             .await
     }
 
+    pub async fn get_stop_point(&self, stop_id: &str) -> AppResult<StopPoint> {
+        debug!("Fetching stop point: {}", stop_id);
+        self.perform_request(&format!("/StopPoint/{}", stop_id))
+            .await
+    }
+
+    pub async fn get_lift_disruptions(&self) -> AppResult<Vec<LiftDisruption>> {
+        debug!("Fetching lift disruptions");
+        self.perform_request("/Disruptions/Lifts/v2/").await
+    }
+
     pub async fn get_disruptions_by_mode(&self, mode: &str) -> AppResult<Vec<Disruption>> {
         debug!("Fetching disruptions for mode: {}", mode);
         self.perform_request(&format!("/Line/Mode/{}/Disruption", mode))
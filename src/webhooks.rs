@@ -0,0 +1,763 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio::task::AbortHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::changes::DisruptionFeed;
+use crate::error::{AppError, AppResult};
+use crate::models::{DisruptionChange, Line, StatusChange};
+use crate::routes::disruption::{parse_modes, ALLOWED_MODES};
+use crate::tfl::TflClient;
+
+// Signed webhook delivery of disruption changes (from the change feed) and line
+// status changes. Subscriptions come from the JSON file named by TB8_WEBHOOKS and
+// the admin API, which writes them back to that file. Each subscription has its
+// own worker, so a slow endpoint only delays its own deliveries; a delivery is
+// retried with backoff, then recorded in the dead-letter log.
+
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// Deliveries waiting per subscription before new ones are dead-lettered
+const QUEUE_CAPACITY: usize = 100;
+const MAX_DEAD_LETTERS: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    // Assigned on registration if not given
+    #[serde(default)]
+    pub id: String,
+    pub url: String,
+    // Key for the `X-Tb8-Signature` HMAC
+    pub secret: String,
+    // Empty filters match everything
+    #[serde(default)]
+    pub modes: Vec<String>,
+    #[serde(default)]
+    pub lines: Vec<String>,
+    // Status severity descriptions, e.g. "Severe Delays". Disruption changes have
+    // no severity, so are only sent to subscriptions without this filter.
+    #[serde(default)]
+    pub severities: Vec<String>,
+}
+
+impl WebhookConfig {
+    fn validate(&mut self) -> AppResult<()> {
+        reqwest::Url::parse(&self.url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| AppError::ParseError(format!("Invalid webhook url: {}", self.url)))?;
+        if self.secret.is_empty() {
+            return Err(AppError::ParseError(
+                "Webhook secret is required".to_string(),
+            ));
+        }
+        if !self.modes.is_empty() {
+            self.modes = parse_modes(&self.modes.join(","))?;
+        }
+        if self.id.is_empty() {
+            self.id = new_id(&self.url);
+        }
+        Ok(())
+    }
+
+    fn matches(&self, event: &WebhookEvent) -> bool {
+        let mode = self.modes.is_empty() || self.modes.iter().any(|mode| *mode == event.mode());
+        let line = self.lines.is_empty()
+            || event
+                .line_ids()
+                .iter()
+                .any(|line_id| self.lines.iter().any(|line| line == line_id));
+        let severity = self.severities.is_empty()
+            || event.severity().is_some_and(|severity| {
+                self.severities
+                    .iter()
+                    .any(|wanted| wanted.eq_ignore_ascii_case(severity))
+            });
+        mode && line && severity
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum WebhookEvent {
+    Disruption(DisruptionChange),
+    Status(StatusChange),
+}
+
+impl WebhookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::Disruption(_) => "disruption",
+            WebhookEvent::Status(_) => "status",
+        }
+    }
+
+    fn mode(&self) -> &str {
+        match self {
+            WebhookEvent::Disruption(change) => &change.mode,
+            WebhookEvent::Status(change) => &change.mode,
+        }
+    }
+
+    fn line_ids(&self) -> Vec<&str> {
+        match self {
+            WebhookEvent::Disruption(change) => change
+                .disruption
+                .affected_routes
+                .iter()
+                .filter_map(|route| route.line_id.as_deref())
+                .collect(),
+            WebhookEvent::Status(change) => vec![change.line_id.as_str()],
+        }
+    }
+
+    fn severity(&self) -> Option<&str> {
+        match self {
+            WebhookEvent::Disruption(_) => None,
+            WebhookEvent::Status(change) => change.status_severity_description.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DeliveryMetrics {
+    pub delivered: u64,
+    pub retries: u64,
+    #[serde(rename = "deadLettered")]
+    pub dead_lettered: u64,
+    pub queued: usize,
+    #[serde(rename = "lastDeliveredAt")]
+    pub last_delivered_at: Option<DateTime<Utc>>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+}
+
+// A subscription as listed by the admin API, without its secret
+#[derive(Debug, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub modes: Vec<String>,
+    pub lines: Vec<String>,
+    pub severities: Vec<String>,
+    pub metrics: DeliveryMetrics,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    #[serde(rename = "deliveryId")]
+    pub delivery_id: String,
+    #[serde(rename = "webhookId")]
+    pub webhook_id: String,
+    pub url: String,
+    pub event: String,
+    pub attempts: u32,
+    pub error: String,
+    #[serde(rename = "failedAt")]
+    pub failed_at: DateTime<Utc>,
+    pub payload: Value,
+}
+
+struct Delivery {
+    id: String,
+    event: &'static str,
+    body: Arc<Vec<u8>>,
+}
+
+struct Endpoint {
+    config: WebhookConfig,
+    queue: mpsc::Sender<Delivery>,
+    metrics: Arc<Mutex<DeliveryMetrics>>,
+    worker: AbortHandle,
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.worker.abort();
+    }
+}
+
+pub struct Webhooks {
+    client: reqwest::Client,
+    config_path: Option<PathBuf>,
+    endpoints: Mutex<BTreeMap<String, Endpoint>>,
+    dead_letters: Arc<Mutex<VecDeque<DeadLetter>>>,
+    deliveries: AtomicU64,
+}
+
+impl Webhooks {
+    // Load subscriptions from TB8_WEBHOOKS, if set, and start delivering changes
+    pub fn start(tfl_client: Arc<TflClient>, feed: Arc<DisruptionFeed>) -> Arc<Self> {
+        let config_path = std::env::var("TB8_WEBHOOKS").ok().map(PathBuf::from);
+        let webhooks = Arc::new(Self::new(config_path));
+
+        match webhooks.load() {
+            Ok(count) if count > 0 => info!("Loaded {} webhook subscriptions", count),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to load webhook subscriptions: {}", e),
+        }
+
+        tokio::spawn(forward_disruptions(Arc::downgrade(&webhooks), feed));
+        tokio::spawn(poll_statuses(Arc::downgrade(&webhooks), tfl_client));
+        webhooks
+    }
+
+    fn new(config_path: Option<PathBuf>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            config_path,
+            endpoints: Mutex::new(BTreeMap::new()),
+            dead_letters: Arc::new(Mutex::new(VecDeque::new())),
+            deliveries: AtomicU64::new(0),
+        }
+    }
+
+    fn load(&self) -> AppResult<usize> {
+        let Some(path) = &self.config_path else {
+            return Ok(0);
+        };
+        if !path.exists() {
+            return Ok(0);
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| AppError::InternalError(format!("{}: {}", path.display(), e)))?;
+        let configs: Vec<WebhookConfig> = serde_json::from_str(&text)
+            .map_err(|e| AppError::ParseError(format!("{}: {}", path.display(), e)))?;
+
+        let count = configs.len();
+        let mut endpoints = self.endpoints.lock().unwrap();
+        for mut config in configs {
+            config.validate()?;
+            endpoints.insert(config.id.clone(), self.endpoint(config));
+        }
+        Ok(count)
+    }
+
+    fn save(&self, configs: Vec<&WebhookConfig>) -> AppResult<()> {
+        let Some(path) = &self.config_path else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(&configs)
+            .map_err(|e| AppError::InternalError(e.to_string()))?;
+        std::fs::write(path, text)
+            .map_err(|e| AppError::InternalError(format!("{}: {}", path.display(), e)))
+    }
+
+    fn endpoint(&self, config: WebhookConfig) -> Endpoint {
+        let (queue, deliveries) = mpsc::channel(QUEUE_CAPACITY);
+        let metrics = Arc::new(Mutex::new(DeliveryMetrics::default()));
+        let worker = tokio::spawn(deliver(
+            self.client.clone(),
+            config.clone(),
+            deliveries,
+            metrics.clone(),
+            self.dead_letters.clone(),
+        ));
+        Endpoint {
+            config,
+            queue,
+            metrics,
+            worker: worker.abort_handle(),
+        }
+    }
+
+    // Subscriptions are saved before they take effect, so a failed save changes
+    // nothing
+    pub fn register(&self, mut config: WebhookConfig) -> AppResult<Webhook> {
+        config.validate()?;
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if endpoints.contains_key(&config.id) {
+            return Err(AppError::Conflict(format!(
+                "Webhook already exists: {}",
+                config.id
+            )));
+        }
+        let mut configs: Vec<&WebhookConfig> = endpoints.values().map(|e| &e.config).collect();
+        configs.push(&config);
+        self.save(configs)?;
+
+        let endpoint = self.endpoint(config);
+        let webhook = view(&endpoint);
+        endpoints.insert(webhook.id.clone(), endpoint);
+        Ok(webhook)
+    }
+
+    pub fn remove(&self, id: &str) -> AppResult<()> {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if !endpoints.contains_key(id) {
+            return Err(AppError::NotFound(format!("Webhook not found: {}", id)));
+        }
+        self.save(
+            endpoints
+                .values()
+                .map(|e| &e.config)
+                .filter(|config| config.id != id)
+                .collect(),
+        )?;
+        endpoints.remove(id);
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<Webhook> {
+        let endpoints = self.endpoints.lock().unwrap();
+        endpoints.values().map(view).collect()
+    }
+
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.endpoints.lock().unwrap().is_empty()
+    }
+
+    // Queue an event for every subscription it matches
+    fn dispatch(&self, event: WebhookEvent) {
+        let endpoints = self.endpoints.lock().unwrap();
+        let mut matching = endpoints
+            .values()
+            .filter(|endpoint| endpoint.config.matches(&event))
+            .peekable();
+        if matching.peek().is_none() {
+            return;
+        }
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => Arc::new(body),
+            Err(e) => return warn!("Failed to serialize webhook event: {}", e),
+        };
+
+        for endpoint in matching {
+            let delivery = Delivery {
+                id: format!("{:x}", self.deliveries.fetch_add(1, Ordering::Relaxed) + 1),
+                event: event.name(),
+                body: body.clone(),
+            };
+            if let Err(mpsc::error::TrySendError::Full(delivery)) =
+                endpoint.queue.try_send(delivery)
+            {
+                let error = "Delivery queue full".to_string();
+                endpoint.metrics.lock().unwrap().dead_lettered += 1;
+                dead_letter(&self.dead_letters, &endpoint.config, &delivery, 0, error);
+            }
+        }
+    }
+}
+
+fn view(endpoint: &Endpoint) -> Webhook {
+    let metrics = endpoint.metrics.lock().unwrap();
+    Webhook {
+        id: endpoint.config.id.clone(),
+        url: endpoint.config.url.clone(),
+        modes: endpoint.config.modes.clone(),
+        lines: endpoint.config.lines.clone(),
+        severities: endpoint.config.severities.clone(),
+        metrics: DeliveryMetrics {
+            delivered: metrics.delivered,
+            retries: metrics.retries,
+            dead_lettered: metrics.dead_lettered,
+            queued: QUEUE_CAPACITY - endpoint.queue.capacity(),
+            last_delivered_at: metrics.last_delivered_at,
+            last_error: metrics.last_error.clone(),
+        },
+    }
+}
+
+fn new_id(url: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    Utc::now().timestamp_nanos_opt().hash(&mut hasher);
+    format!("wh-{:016x}", hasher.finish())
+}
+
+// Hex HMAC-SHA256 of `{timestamp}.{body}`, sent as `X-Tb8-Signature: sha256=...`
+// so receivers can check both the payload and its freshness
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Wait before the given retry: 1s, 2s, 4s, ... up to a minute
+fn backoff(retry: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+async fn deliver(
+    client: reqwest::Client,
+    config: WebhookConfig,
+    mut deliveries: mpsc::Receiver<Delivery>,
+    metrics: Arc<Mutex<DeliveryMetrics>>,
+    dead_letters: Arc<Mutex<VecDeque<DeadLetter>>>,
+) {
+    while let Some(delivery) = deliveries.recv().await {
+        let mut attempt = 0;
+        let error = loop {
+            attempt += 1;
+            match post(&client, &config, &delivery).await {
+                Ok(()) => break None,
+                Err(e) if attempt == MAX_ATTEMPTS => break Some(e),
+                Err(e) => {
+                    metrics.lock().unwrap().retries += 1;
+                    warn!(
+                        "Webhook {} delivery {} failed: {}",
+                        config.id, delivery.id, e
+                    );
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+            }
+        };
+
+        let mut metrics = metrics.lock().unwrap();
+        match error {
+            None => {
+                metrics.delivered += 1;
+                metrics.last_delivered_at = Some(Utc::now());
+            }
+            Some(error) => {
+                metrics.dead_lettered += 1;
+                metrics.last_error = Some(error.clone());
+                dead_letter(&dead_letters, &config, &delivery, attempt, error);
+            }
+        }
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    delivery: &Delivery,
+) -> Result<(), String> {
+    let timestamp = Utc::now().timestamp();
+    let response = client
+        .post(&config.url)
+        .header("Content-Type", "application/json")
+        .header("X-Tb8-Event", delivery.event)
+        .header("X-Tb8-Delivery", &delivery.id)
+        .header("X-Tb8-Timestamp", timestamp.to_string())
+        .header(
+            "X-Tb8-Signature",
+            format!(
+                "sha256={}",
+                signature(&config.secret, timestamp, &delivery.body)
+            ),
+        )
+        .body(delivery.body.as_ref().clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+fn dead_letter(
+    dead_letters: &Mutex<VecDeque<DeadLetter>>,
+    config: &WebhookConfig,
+    delivery: &Delivery,
+    attempts: u32,
+    error: String,
+) {
+    warn!(
+        "Webhook {} delivery {} dead-lettered after {} attempts: {}",
+        config.id, delivery.id, attempts, error
+    );
+    let mut dead_letters = dead_letters.lock().unwrap();
+    if dead_letters.len() == MAX_DEAD_LETTERS {
+        dead_letters.pop_front();
+    }
+    dead_letters.push_back(DeadLetter {
+        delivery_id: delivery.id.clone(),
+        webhook_id: config.id.clone(),
+        url: config.url.clone(),
+        event: delivery.event.to_string(),
+        attempts,
+        error,
+        failed_at: Utc::now(),
+        payload: serde_json::from_slice(&delivery.body).unwrap_or(Value::Null),
+    });
+}
+
+async fn forward_disruptions(webhooks: std::sync::Weak<Webhooks>, feed: Arc<DisruptionFeed>) {
+    let mut receiver = feed.subscribe();
+    let mut last_sequence = 0;
+    loop {
+        let changes = match receiver.recv().await {
+            Ok(change) => vec![change],
+            // Fell behind; catch up from the changes the feed keeps
+            Err(RecvError::Lagged(_)) => feed.since_sequence(last_sequence),
+            Err(RecvError::Closed) => return,
+        };
+        let Some(webhooks) = webhooks.upgrade() else {
+            return;
+        };
+        for change in changes {
+            if change.sequence > last_sequence {
+                last_sequence = change.sequence;
+                // Disruptions already current at startup aren't news, as with statuses
+                if !change.baseline {
+                    webhooks.dispatch(WebhookEvent::Disruption(change));
+                }
+            }
+        }
+    }
+}
+
+// Poll line statuses while there are subscriptions, reporting each line whose
+// headline status has changed since the previous poll
+async fn poll_statuses(webhooks: std::sync::Weak<Webhooks>, tfl_client: Arc<TflClient>) {
+    let mut ticker = tokio::time::interval(STATUS_POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut previous: HashMap<String, HeadlineStatus> = HashMap::new();
+
+    loop {
+        ticker.tick().await;
+        match webhooks.upgrade() {
+            None => return,
+            // Nothing to compare against once subscriptions are registered again
+            Some(webhooks) if webhooks.is_empty() => {
+                previous.clear();
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        for mode in ALLOWED_MODES {
+            let lines = match tfl_client.get_line_statuses_by_mode(mode).await {
+                Ok(lines) => lines,
+                Err(e) => {
+                    warn!("Line status poll for {} failed: {}", mode, e);
+                    continue;
+                }
+            };
+            let Some(webhooks) = webhooks.upgrade() else {
+                return;
+            };
+            for change in status_changes(&mut previous, mode, &lines, Utc::now()) {
+                webhooks.dispatch(WebhookEvent::Status(change));
+            }
+        }
+    }
+}
+
+type HeadlineStatus = (Option<i32>, Option<String>, Option<String>);
+
+fn status_changes(
+    previous: &mut HashMap<String, HeadlineStatus>,
+    mode: &str,
+    lines: &[Line],
+    now: DateTime<Utc>,
+) -> Vec<StatusChange> {
+    let mut changes = Vec::new();
+    for line in lines {
+        // TfL lists a line's most severe status first
+        let status = line.line_statuses.first();
+        let current: HeadlineStatus = (
+            status.and_then(|s| s.status_severity),
+            status.and_then(|s| s.status_severity_description.clone()),
+            status.and_then(|s| s.reason.clone()),
+        );
+        match previous.insert(line.id.clone(), current.clone()) {
+            // The first poll is only a baseline
+            None => {}
+            Some(before) if before == current => {}
+            Some(before) => changes.push(StatusChange {
+                line_id: line.id.clone(),
+                line_name: line.name.clone(),
+                mode: mode.to_string(),
+                status_severity: current.0,
+                status_severity_description: current.1,
+                reason: current.2,
+                previous_status_severity_description: before.1,
+                detected_at: now,
+            }),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("secret", 1700000000, br#"{"event":"status"}"#),
+            "0b7fbe4d03705f0062618530b4d0458491a5b81d5fd8f1366200257ca42a3f28"
+        );
+    }
+
+    #[test]
+    fn test_filters_and_status_changes() {
+        let config: WebhookConfig = serde_json::from_value(json!({
+            "url": "https://example.com/hook",
+            "secret": "s",
+            "lines": ["victoria"],
+            "severities": ["severe delays"],
+        }))
+        .unwrap();
+
+        let line = |severity: i32, description: &str| -> Line {
+            serde_json::from_value(json!({
+                "id": "victoria",
+                "name": "Victoria",
+                "modeName": "tube",
+                "lineStatuses": [{
+                    "statusSeverity": severity,
+                    "statusSeverityDescription": description,
+                }],
+            }))
+            .unwrap()
+        };
+        let now = Utc::now();
+        let mut previous = HashMap::new();
+        assert!(status_changes(&mut previous, "tube", &[line(10, "Good Service")], now).is_empty());
+        assert!(status_changes(&mut previous, "tube", &[line(10, "Good Service")], now).is_empty());
+
+        let changes = status_changes(&mut previous, "tube", &[line(6, "Severe Delays")], now);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].previous_status_severity_description.as_deref(),
+            Some("Good Service")
+        );
+        assert!(config.matches(&WebhookEvent::Status(changes[0].clone())));
+
+        let mut minor = changes[0].clone();
+        minor.status_severity_description = Some("Minor Delays".to_string());
+        assert!(!config.matches(&WebhookEvent::Status(minor)));
+        let mut elsewhere = changes[0].clone();
+        elsewhere.line_id = "central".to_string();
+        assert!(!config.matches(&WebhookEvent::Status(elsewhere)));
+
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(10), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_register_saves_first() {
+        let config = |id: &str| -> WebhookConfig {
+            serde_json::from_value(json!({
+                "id": id,
+                "url": "https://example.com/hook",
+                "secret": "s",
+            }))
+            .unwrap()
+        };
+
+        let dir = std::env::temp_dir().join(format!("tb8-webhooks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let webhooks = Webhooks::new(Some(dir.join("webhooks.json")));
+        webhooks.register(config("a")).unwrap();
+        assert!(matches!(
+            webhooks.register(config("a")),
+            Err(AppError::Conflict(_))
+        ));
+
+        // The file can't be written, so nothing changes
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(webhooks.register(config("b")).is_err());
+        assert!(webhooks.remove("a").is_err());
+        let ids: Vec<String> = webhooks.list().into_iter().map(|w| w.id).collect();
+        assert_eq!(ids, ["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_with_retry() {
+        use axum::{http::HeaderMap, http::StatusCode, routing::post, Router};
+
+        // Fails the first attempt, then records what it receives
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let requests = received.clone();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| async move {
+                let mut requests = requests.lock().unwrap();
+                requests.push((headers, body));
+                if requests.len() == 1 {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let webhooks = Webhooks::new(None);
+        webhooks
+            .register(WebhookConfig {
+                id: String::new(),
+                url,
+                secret: "secret".to_string(),
+                modes: vec!["tube".to_string()],
+                lines: Vec::new(),
+                severities: Vec::new(),
+            })
+            .unwrap();
+
+        let change: StatusChange = serde_json::from_value(json!({
+            "lineId": "victoria",
+            "lineName": "Victoria",
+            "mode": "tube",
+            "statusSeverity": 6,
+            "statusSeverityDescription": "Severe Delays",
+            "detectedAt": "2024-01-01T12:00:00Z",
+        }))
+        .unwrap();
+        webhooks.dispatch(WebhookEvent::Status(change.clone()));
+        // Filtered out by mode
+        webhooks.dispatch(WebhookEvent::Status(StatusChange {
+            mode: "dlr".to_string(),
+            ..change
+        }));
+
+        tokio::time::sleep(INITIAL_BACKOFF + Duration::from_millis(500)).await;
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap();
+        assert_eq!(header("x-tb8-event"), "status");
+        let timestamp: i64 = header("x-tb8-timestamp").parse().unwrap();
+        assert_eq!(
+            header("x-tb8-signature"),
+            format!("sha256={}", signature("secret", timestamp, body.as_bytes()))
+        );
+        assert!(body.starts_with(r#"{"event":"status","lineId":"victoria""#));
+
+        let metrics = &webhooks.list()[0].metrics;
+        assert_eq!((metrics.delivered, metrics.retries), (1, 1));
+    }
+}